@@ -0,0 +1,124 @@
+use {
+    evalexpr::{
+        ContextWithMutableFunctions, EvalexprError, EvalexprResult, Function, HashMapContext, Value,
+    },
+    std::f64::consts::PI,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleUnit {
+    Rad,
+    Deg,
+    Grad,
+}
+
+impl AngleUnit {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rad" => Some(Self::Rad),
+            "deg" => Some(Self::Deg),
+            "grad" => Some(Self::Grad),
+            _ => None,
+        }
+    }
+
+    /// How many of this unit make up one radian
+    pub fn per_radian(self) -> f64 {
+        match self {
+            Self::Rad => 1.0,
+            Self::Deg => 180.0 / PI,
+            Self::Grad => 200.0 / PI,
+        }
+    }
+
+    /// How many of this unit make up half a turn
+    pub fn half_turn(self) -> f64 {
+        self.per_radian() * PI
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rad => "rad",
+            Self::Deg => "deg",
+            Self::Grad => "grad",
+        }
+    }
+}
+
+/// Functions converting an angle from the active unit to another one
+const CONVERSIONS: &[(&str, AngleUnit)] = &[("to_deg", AngleUnit::Deg), ("to_rad", AngleUnit::Rad)];
+
+const TRIG: &[(&str, fn(f64) -> f64)] = &[
+    ("math::sin", f64::sin),
+    ("math::cos", f64::cos),
+    ("math::tan", f64::tan),
+];
+
+const INVERSE_TRIG: &[(&str, fn(f64) -> f64)] = &[
+    ("math::asin", f64::asin),
+    ("math::acos", f64::acos),
+    ("math::atan", f64::atan),
+];
+
+/// `v` times `factor`, converting it from one angle unit to another
+fn scale(v: &Value, factor: f64) -> EvalexprResult<Value> {
+    Ok(Value::Float(v.as_number()? * factor))
+}
+
+/// Register the `angle::` functions angle literals are rewritten into, which convert them to
+/// `unit`, and `to_deg` and `to_rad`. Replace the trigonometric functions of `context` with
+/// ones working in `unit`.
+pub fn register(context: &mut HashMapContext, unit: AngleUnit) {
+    let k = unit.per_radian();
+    for &(name, to) in CONVERSIONS {
+        let factor = to.per_radian() / k;
+        context
+            .set_function(
+                name.into(),
+                Function::new(Box::new(move |v| scale(v, factor))),
+            )
+            .unwrap();
+    }
+    for &from in &[AngleUnit::Rad, AngleUnit::Deg, AngleUnit::Grad] {
+        let factor = k / from.per_radian();
+        context
+            .set_function(
+                format!("angle::{}", from.name()),
+                Function::new(Box::new(move |v| scale(v, factor))),
+            )
+            .unwrap();
+    }
+
+    if unit == AngleUnit::Rad {
+        return;
+    }
+
+    for &(name, f) in TRIG {
+        context
+            .set_function(
+                name.into(),
+                Function::new(Box::new(move |v| Ok(Value::Float(f(v.as_number()? / k))))),
+            )
+            .unwrap();
+    }
+    for &(name, f) in INVERSE_TRIG {
+        context
+            .set_function(
+                name.into(),
+                Function::new(Box::new(move |v| Ok(Value::Float(f(v.as_number()?) * k)))),
+            )
+            .unwrap();
+    }
+    context
+        .set_function(
+            "math::atan2".into(),
+            Function::new(Box::new(move |v| match v.as_tuple()?.as_slice() {
+                [y, x] => Ok(Value::Float(y.as_number()?.atan2(x.as_number()?) * k)),
+                args => Err(EvalexprError::WrongFunctionArgumentAmount {
+                    expected: 2,
+                    actual: args.len(),
+                }),
+            })),
+        )
+        .unwrap();
+}
@@ -0,0 +1,930 @@
+use {
+    crate::{
+        calculus, currency, dates, finance, percent, preprocess, sequence, solver, stats, symbolic,
+    },
+    evalexpr::{EvalexprResult, Value},
+};
+
+pub type BuiltinFn = fn(&Value) -> EvalexprResult<Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Function,
+    Constant,
+}
+
+/// A function or constant available to documents, along with its help entry
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub arity: &'static str,
+    pub types: &'static str,
+    pub description: &'static str,
+    pub example: &'static str,
+    /// `None` for builtins provided by evalexpr itself
+    pub function: Option<BuiltinFn>,
+}
+
+impl Builtin {
+    /// Text inserted into the editor when picked from the help page
+    pub fn snippet(&self) -> String {
+        match self.kind {
+            Kind::Function => format!(
+                "{}()",
+                preprocess::written_name(self.name).unwrap_or(self.name)
+            ),
+            Kind::Constant => self.name.to_string(),
+        }
+    }
+}
+
+macro_rules! builtin {
+    ($name:expr, $arity:expr, $types:expr, $desc:expr, $example:expr) => {
+        builtin!($name, $arity, $types, $desc, $example, None)
+    };
+    ($name:expr, $arity:expr, $types:expr, $desc:expr, $example:expr, $func:expr) => {
+        Builtin {
+            name: $name,
+            kind: Kind::Function,
+            arity: $arity,
+            types: $types,
+            description: $desc,
+            example: $example,
+            function: $func,
+        }
+    };
+}
+
+macro_rules! constant {
+    ($name:expr, $desc:expr) => {
+        Builtin {
+            name: $name,
+            kind: Kind::Constant,
+            arity: "-",
+            types: "Float",
+            description: $desc,
+            example: $name,
+            function: None,
+        }
+    };
+}
+
+const TABLES: &[&[Builtin]] = &[
+    EVALEXPR, MATH, STATS, FINANCE, PERCENT, DATES, CURRENCY, SYMBOLIC, SOLVER, CALCULUS,
+    SEQUENCES, CONSTANTS,
+];
+
+pub fn all() -> impl Iterator<Item = &'static Builtin> {
+    TABLES.iter().flat_map(|t| t.iter())
+}
+
+/// Builtins implemented by this crate, to be registered in the evaluation context
+pub fn native() -> impl Iterator<Item = (&'static str, BuiltinFn)> {
+    all().filter_map(|b| b.function.map(|f| (b.name, f)))
+}
+
+const EVALEXPR: &[Builtin] = &[
+    builtin!(
+        "min",
+        ">= 1",
+        "Numeric",
+        "Returns the minimum of the arguments",
+        "min(3, 1, 2)"
+    ),
+    builtin!(
+        "max",
+        ">= 1",
+        "Numeric",
+        "Returns the maximum of the arguments",
+        "max(3, 1, 2)"
+    ),
+    builtin!(
+        "len",
+        "1",
+        "String/Tuple",
+        "Returns the character length of a string, or the amount of elements in a tuple (not recursively)",
+        "len((1, 2, 3))"
+    ),
+    builtin!(
+        "floor",
+        "1",
+        "Numeric",
+        "Returns the largest integer less than or equal to a number",
+        "floor(2.7)"
+    ),
+    builtin!(
+        "round",
+        "1",
+        "Numeric",
+        "Returns the nearest integer to a number, rounding half-way cases away from 0",
+        "round(2.5)"
+    ),
+    builtin!(
+        "ceil",
+        "1",
+        "Numeric",
+        "Returns the smallest integer greater than or equal to a number",
+        "ceil(2.1)"
+    ),
+    builtin!(
+        "if",
+        "3",
+        "Boolean, Any, Any",
+        "If the first argument is true, returns the second argument, otherwise the third",
+        "if(1 < 2, 10, 20)"
+    ),
+    builtin!(
+        "str::regex_matches",
+        "2",
+        "String, String",
+        "Returns true if the first argument matches the regex in the second argument",
+        "str::regex_matches(\"abc\", \"b\")"
+    ),
+    builtin!(
+        "str::regex_replace",
+        "3",
+        "String, String, String",
+        "Returns the first argument with all matches of the regex in the second argument replaced by the third argument",
+        "str::regex_replace(\"abc\", \"b\", \"x\")"
+    ),
+    builtin!(
+        "str::to_lowercase",
+        "1",
+        "String",
+        "Returns the lower-case version of the string",
+        "str::to_lowercase(\"ABC\")"
+    ),
+    builtin!(
+        "str::to_uppercase",
+        "1",
+        "String",
+        "Returns the upper-case version of the string",
+        "str::to_uppercase(\"abc\")"
+    ),
+    builtin!(
+        "str::trim",
+        "1",
+        "String",
+        "Strips whitespace from the start and the end of the string",
+        "str::trim(\"  abc  \")"
+    ),
+];
+
+const MATH: &[Builtin] = &[
+    builtin!(
+        "math::ln",
+        "1",
+        "Numeric",
+        "Returns the natural logarithm of the number",
+        "math::ln(E)"
+    ),
+    builtin!(
+        "math::log",
+        "2",
+        "Numeric, Numeric",
+        "Returns the logarithm of the number with respect to an arbitrary base",
+        "math::log(8, 2)"
+    ),
+    builtin!(
+        "math::log2",
+        "1",
+        "Numeric",
+        "Returns the base 2 logarithm of the number",
+        "math::log2(8)"
+    ),
+    builtin!(
+        "math::log10",
+        "1",
+        "Numeric",
+        "Returns the base 10 logarithm of the number",
+        "math::log10(1000)"
+    ),
+    builtin!(
+        "math::exp",
+        "1",
+        "Numeric",
+        "Returns e^(number)",
+        "math::exp(1)"
+    ),
+    builtin!(
+        "math::exp2",
+        "1",
+        "Numeric",
+        "Returns 2^(number)",
+        "math::exp2(10)"
+    ),
+    builtin!(
+        "math::pow",
+        "2",
+        "Numeric, Numeric",
+        "Raises a number to the power of the other number",
+        "math::pow(2, 0.5)"
+    ),
+    builtin!(
+        "math::cos",
+        "1",
+        "Numeric",
+        "Computes the cosine of an angle in the angle unit, radians by default",
+        "math::cos(180 deg)"
+    ),
+    builtin!(
+        "math::acos",
+        "1",
+        "Numeric",
+        "Computes the arccosine of a number, as an angle in the angle unit",
+        "math::acos(0)"
+    ),
+    builtin!(
+        "math::cosh",
+        "1",
+        "Numeric",
+        "Hyperbolic cosine function",
+        "math::cosh(1)"
+    ),
+    builtin!(
+        "math::acosh",
+        "1",
+        "Numeric",
+        "Inverse hyperbolic cosine function",
+        "math::acosh(2)"
+    ),
+    builtin!(
+        "math::sin",
+        "1",
+        "Numeric",
+        "Computes the sine of an angle in the angle unit, radians by default",
+        "math::sin(90 deg)"
+    ),
+    builtin!(
+        "math::asin",
+        "1",
+        "Numeric",
+        "Computes the arcsine of a number, as an angle in the angle unit",
+        "math::asin(1)"
+    ),
+    builtin!(
+        "math::sinh",
+        "1",
+        "Numeric",
+        "Hyperbolic sine function",
+        "math::sinh(1)"
+    ),
+    builtin!(
+        "math::asinh",
+        "1",
+        "Numeric",
+        "Inverse hyperbolic sine function",
+        "math::asinh(1)"
+    ),
+    builtin!(
+        "math::tan",
+        "1",
+        "Numeric",
+        "Computes the tangent of an angle in the angle unit, radians by default",
+        "math::tan(45 deg)"
+    ),
+    builtin!(
+        "math::atan",
+        "1",
+        "Numeric",
+        "Computes the arctangent of a number, as an angle in the angle unit",
+        "math::atan(1)"
+    ),
+    builtin!(
+        "math::atan2",
+        "2",
+        "Numeric, Numeric",
+        "Computes the four quadrant arctangent of y and x, as an angle in the angle unit",
+        "math::atan2(1, -1)"
+    ),
+    builtin!(
+        "to_deg",
+        "1",
+        "Numeric",
+        "Converts an angle in the angle unit to degrees. Angle literals like 30 deg, 30°, 0.5 rad and 50 grad are converted to the angle unit",
+        "to_deg(math::atan(1))"
+    ),
+    builtin!(
+        "to_rad",
+        "1",
+        "Numeric",
+        "Converts an angle in the angle unit to radians",
+        "to_rad(90 deg)"
+    ),
+    builtin!(
+        "math::tanh",
+        "1",
+        "Numeric",
+        "Hyperbolic tangent function",
+        "math::tanh(1)"
+    ),
+    builtin!(
+        "math::atanh",
+        "1",
+        "Numeric",
+        "Inverse hyperbolic tangent function",
+        "math::atanh(0.5)"
+    ),
+    builtin!(
+        "math::sqrt",
+        "1",
+        "Numeric",
+        "Returns the square root of a number",
+        "math::sqrt(2)"
+    ),
+    builtin!(
+        "math::cbrt",
+        "1",
+        "Numeric",
+        "Returns the cube root of a number",
+        "math::cbrt(27)"
+    ),
+    builtin!(
+        "math::hypot",
+        "2",
+        "Numeric, Numeric",
+        "Calculates the length of the hypotenuse of a right-angle triangle given legs of length x and y",
+        "math::hypot(3, 4)"
+    ),
+    builtin!(
+        "math::abs",
+        "1",
+        "Numeric",
+        "Returns the absolute value of a number",
+        "math::abs(-3)"
+    ),
+    builtin!(
+        "math::is_nan",
+        "1",
+        "Numeric",
+        "Returns true if the argument is the floating-point value NaN",
+        "math::is_nan(0.0 / 0.0)"
+    ),
+    builtin!(
+        "math::is_finite",
+        "1",
+        "Numeric",
+        "Returns true if the argument is neither infinite nor NaN",
+        "math::is_finite(1.0)"
+    ),
+    builtin!(
+        "math::is_infinite",
+        "1",
+        "Numeric",
+        "Returns true if the argument is positive or negative infinity",
+        "math::is_infinite(1.0 / 0.0)"
+    ),
+    builtin!(
+        "math::is_normal",
+        "1",
+        "Numeric",
+        "Returns true if the argument is neither zero, infinite, subnormal nor NaN",
+        "math::is_normal(1.0)"
+    ),
+];
+
+const STATS: &[Builtin] = &[
+    builtin!(
+        "stats::sum",
+        ">= 1",
+        "Numeric/Tuple",
+        "Returns the sum of the values",
+        "stats::sum(1, 2, 3)",
+        Some(stats::sum)
+    ),
+    builtin!(
+        "stats::mean",
+        ">= 1",
+        "Numeric/Tuple",
+        "Returns the arithmetic mean of the values",
+        "stats::mean(1, 2, 3, 4)",
+        Some(stats::mean)
+    ),
+    builtin!(
+        "stats::median",
+        ">= 1",
+        "Numeric/Tuple",
+        "Returns the middle value, or the mean of the two middle values",
+        "stats::median(3, 1, 4, 1, 5)",
+        Some(stats::median)
+    ),
+    builtin!(
+        "stats::mode",
+        ">= 1",
+        "Numeric/Tuple",
+        "Returns the most common value, the smallest one on ties",
+        "stats::mode(1, 2, 2, 3)",
+        Some(stats::mode)
+    ),
+    builtin!(
+        "stats::variance",
+        ">= 2",
+        "Numeric/Tuple",
+        "Returns the sample variance of the values",
+        "stats::variance(2, 4, 4, 4, 5, 5, 7, 9)",
+        Some(stats::variance)
+    ),
+    builtin!(
+        "stats::pvariance",
+        ">= 1",
+        "Numeric/Tuple",
+        "Returns the population variance of the values",
+        "stats::pvariance(2, 4, 4, 4, 5, 5, 7, 9)",
+        Some(stats::pvariance)
+    ),
+    builtin!(
+        "stats::stddev",
+        ">= 2",
+        "Numeric/Tuple",
+        "Returns the sample standard deviation of the values",
+        "stats::stddev(2, 4, 4, 4, 5, 5, 7, 9)",
+        Some(stats::stddev)
+    ),
+    builtin!(
+        "stats::pstddev",
+        ">= 1",
+        "Numeric/Tuple",
+        "Returns the population standard deviation of the values",
+        "stats::pstddev(2, 4, 4, 4, 5, 5, 7, 9)",
+        Some(stats::pstddev)
+    ),
+    builtin!(
+        "stats::percentile",
+        "2",
+        "Tuple, Numeric",
+        "Returns the given percentile (0 to 100) of the values, interpolating between them",
+        "stats::percentile((1, 2, 3, 4, 5), 90)",
+        Some(stats::percentile)
+    ),
+    builtin!(
+        "stats::quantile",
+        "2",
+        "Tuple, Numeric",
+        "Returns the given quantile (0 to 1) of the values, interpolating between them",
+        "stats::quantile((1, 2, 3, 4, 5), 0.25)",
+        Some(stats::quantile)
+    ),
+    builtin!(
+        "stats::correlation",
+        "2",
+        "Tuple, Tuple",
+        "Returns the Pearson correlation coefficient of two data sets",
+        "stats::correlation((1, 2, 3), (2, 4, 7))",
+        Some(stats::correlation)
+    ),
+    builtin!(
+        "stats::linreg",
+        "2",
+        "Tuple, Tuple",
+        "Fits a line y = a * x + b to the points by least squares, returns (a, b)",
+        "stats::linreg((1, 2, 3), (2, 4, 7))",
+        Some(stats::linreg)
+    ),
+];
+
+const FINANCE: &[Builtin] = &[
+    builtin!(
+        "fin::pmt",
+        "3-5",
+        "Numeric",
+        "Returns the payment per period for a loan or investment, from rate, periods, present value, future value and type (1 to pay at the start of periods). Money paid out is negative",
+        "fin::pmt(0.05 / 12, 360, 200000)",
+        Some(finance::pmt)
+    ),
+    builtin!(
+        "fin::pv",
+        "3-5",
+        "Numeric",
+        "Returns the present value from rate, periods, payment, future value and type",
+        "fin::pv(0.04, 10, -1000)",
+        Some(finance::pv)
+    ),
+    builtin!(
+        "fin::fv",
+        "3-5",
+        "Numeric",
+        "Returns the future value from rate, periods, payment, present value and type",
+        "fin::fv(0.04, 10, -1000)",
+        Some(finance::fv)
+    ),
+    builtin!(
+        "fin::nper",
+        "3-5",
+        "Numeric",
+        "Returns the number of periods from rate, payment, present value, future value and type",
+        "fin::nper(0.01, -100, 1000)",
+        Some(finance::nper)
+    ),
+    builtin!(
+        "fin::rate",
+        "3-6",
+        "Numeric",
+        "Returns the interest rate per period from periods, payment, present value, future value, type and a guess",
+        "fin::rate(10, -100, 800)",
+        Some(finance::rate)
+    ),
+    builtin!(
+        "fin::npv",
+        "2+",
+        "Numeric, Numeric/Tuple",
+        "Returns the net present value of cash flows at the end of periods 1, 2, …",
+        "fin::npv(0.1, -1000, 300, 400, 500)",
+        Some(finance::npv)
+    ),
+    builtin!(
+        "fin::irr",
+        "1-2",
+        "Tuple, Numeric",
+        "Returns the internal rate of return of cash flows, the first one at period 0, with an optional guess",
+        "fin::irr((-1000, 300, 400, 500))",
+        Some(finance::irr)
+    ),
+    builtin!(
+        "fin::xnpv",
+        "3",
+        "Numeric, Tuple, Tuple",
+        "Returns the net present value of cash flows on the given dates, with 365 days a year",
+        "fin::xnpv(0.1, (-1000, 600, 600), (date::parse(\"2024-01-01\"), date::parse(\"2024-07-01\"), date::parse(\"2025-01-01\")))",
+        Some(finance::xnpv)
+    ),
+    builtin!(
+        "fin::amortize",
+        "3",
+        "Numeric",
+        "Returns the repayment schedule of a loan from rate, periods and present value, as (period, payment, interest, principal, balance) rows",
+        "fin::amortize(0.01, 12, 1000)",
+        Some(finance::amortize)
+    ),
+];
+
+const PERCENT: &[Builtin] = &[
+    builtin!(
+        "pct::of",
+        "2",
+        "Numeric, Numeric",
+        "Returns the first number percent of the second. Also written as p% of x, and p% alone is p% of 1",
+        "20% of 350",
+        Some(percent::of)
+    ),
+    builtin!(
+        "pct::add",
+        "2",
+        "Numeric, Numeric",
+        "Returns the first number increased by the second number percent of it. Also written as x + p%",
+        "200 + 15%",
+        Some(percent::add)
+    ),
+    builtin!(
+        "pct::sub",
+        "2",
+        "Numeric, Numeric",
+        "Returns the first number decreased by the second number percent of it. Also written as x - p%",
+        "80 - 25%",
+        Some(percent::sub)
+    ),
+    builtin!(
+        "pct::ratio",
+        "2",
+        "Numeric, Numeric",
+        "Returns how many percent of the second number the first is. Also written as x as % of y",
+        "70 as % of 350",
+        Some(percent::ratio)
+    ),
+];
+
+const DATES: &[Builtin] = &[
+    builtin!(
+        "date::parse",
+        "1-2",
+        "String, String",
+        "Returns the date and time written as 2024-01-31 or 2024-01-31 14:30, in the local or given time zone, as seconds since 1970",
+        "date::parse(\"2024-01-31 14:30\", \"Europe/Paris\")",
+        Some(dates::parse)
+    ),
+    builtin!(
+        "date::now",
+        "0",
+        "-",
+        "Returns the current date and time",
+        "date::now()",
+        Some(dates::now)
+    ),
+    builtin!(
+        "date::today",
+        "0-1",
+        "String",
+        "Returns midnight at the start of today, in the local or given time zone",
+        "date::today()",
+        Some(dates::today)
+    ),
+    builtin!(
+        "date::format",
+        "1-3",
+        "Numeric, String, String",
+        "Formats a date with strftime codes like %Y-%m-%d, in the local or given time zone. Results known to be dates are shown as 2024-01-31 without it",
+        "date::format(date::now(), \"%A %d %B %Y\")",
+        Some(dates::format)
+    ),
+    builtin!(
+        "date::year",
+        "1-2",
+        "Numeric, String",
+        "Returns the year of a date",
+        "date::year(date::now())",
+        Some(dates::year)
+    ),
+    builtin!(
+        "date::month",
+        "1-2",
+        "Numeric, String",
+        "Returns the month of a date, from 1 to 12",
+        "date::month(date::now())",
+        Some(dates::month)
+    ),
+    builtin!(
+        "date::day",
+        "1-2",
+        "Numeric, String",
+        "Returns the day of the month of a date",
+        "date::day(date::now())",
+        Some(dates::day)
+    ),
+    builtin!(
+        "date::weekday",
+        "1-2",
+        "Numeric, String",
+        "Returns the day of the week of a date, 1 for Monday to 7 for Sunday",
+        "date::weekday(date::now())",
+        Some(dates::weekday)
+    ),
+    builtin!(
+        "date::add_months",
+        "2-3",
+        "Numeric, Numeric, String",
+        "Adds calendar months to a date, clamping the day to the length of the month",
+        "date::add_months(date::parse(\"2024-01-31\"), 1)",
+        Some(dates::add_months)
+    ),
+    builtin!(
+        "date::add_days",
+        "2-3",
+        "Numeric, Numeric, String",
+        "Adds calendar days to a date, keeping the time of day across daylight saving changes",
+        "date::add_days(date::parse(\"2024-03-30\"), 1)",
+        Some(dates::add_days)
+    ),
+    builtin!(
+        "date::days_between",
+        "2-3",
+        "Numeric, Numeric, String",
+        "Returns the number of calendar days from the first date to the second",
+        "date::days_between(date::parse(\"2024-01-01\"), date::parse(\"2024-03-01\"))",
+        Some(dates::days_between)
+    ),
+    builtin!(
+        "date::business_days",
+        "2-3",
+        "Numeric, Numeric, String",
+        "Returns the number of Monday to Friday days from the first date up to the second",
+        "date::business_days(date::parse(\"2024-01-01\"), date::parse(\"2024-02-01\"))",
+        Some(dates::business_days)
+    ),
+    builtin!(
+        "date::add_business_days",
+        "2-3",
+        "Numeric, Numeric, String",
+        "Adds Monday to Friday days to a date, skipping weekends",
+        "date::add_business_days(date::today(), 10)",
+        Some(dates::add_business_days)
+    ),
+    builtin!(
+        "date::duration",
+        "1",
+        "Numeric",
+        "Formats a number of seconds as days, hours, minutes and seconds. Results known to be durations are shown this way without it",
+        "date::duration(1 day + 90 minutes)",
+        Some(dates::duration)
+    ),
+    builtin!(
+        "date::span",
+        "1",
+        "Numeric",
+        "Returns a number of seconds as a duration. Numbers followed by seconds, minutes, hours, days or weeks are durations",
+        "date::span(5400)",
+        Some(dates::span)
+    ),
+];
+
+const CURRENCY: &[Builtin] = &[
+    builtin!(
+        "cur::from",
+        "2",
+        "Numeric, String",
+        "Converts an amount in a currency to the base currency of the rates file. Amounts in the currencies of the rates file can also be written as 100 USD, results are shown with their currency",
+        "cur::from(100, \"USD\")",
+        Some(currency::from)
+    ),
+    builtin!(
+        "cur::to",
+        "2",
+        "Numeric, String",
+        "Converts an amount in the base currency to a currency, also written as 100 USD in EUR or 100 USD to EUR",
+        "cur::to(100, \"USD\")",
+        Some(currency::to)
+    ),
+    builtin!(
+        "cur::convert",
+        "3",
+        "Numeric, String, String",
+        "Converts an amount from one currency to another",
+        "cur::convert(100, \"USD\", \"EUR\")",
+        Some(currency::convert)
+    ),
+    builtin!(
+        "cur::rate",
+        "1",
+        "String",
+        "Returns how many units of a currency one unit of the base currency buys",
+        "cur::rate(\"USD\")",
+        Some(currency::exchange_rate)
+    ),
+];
+
+const SYMBOLIC: &[Builtin] = &[
+    builtin!(
+        "sym::diff",
+        "2",
+        "Expression, Variable",
+        "Returns the derivative of the expression with respect to the variable, as text. Written as diff(expression, x)",
+        "diff(x^2 * math::sin(x), x)",
+        Some(symbolic::diff)
+    ),
+    builtin!(
+        "sym::simplify",
+        "1",
+        "Expression",
+        "Returns the expression simplified, as text. Written as simplify(expression)",
+        "simplify(2 * x + x * 3)",
+        Some(symbolic::simplify)
+    ),
+];
+
+const SOLVER: &[Builtin] = &[
+    builtin!(
+        "num::solve",
+        "2 or 4",
+        "Equation, Variable, Numeric, Numeric",
+        "Returns the solutions of the equation between the last two arguments, -100 to 100 by default, or the solution of linear equations in several variables. Written as solve(equation, x)",
+        "solve((x + y == 3, x - y == 1), (x, y))",
+        Some(solver::solve)
+    ),
+    builtin!(
+        "num::root",
+        "2",
+        "Expression, Numeric",
+        "Returns a root of the expression in x close to the number. Written as root(expression, x0)",
+        "root(math::cos(x) - x, 1)",
+        Some(solver::root)
+    ),
+];
+
+const CALCULUS: &[Builtin] = &[
+    builtin!(
+        "num::integrate",
+        "3-4",
+        "Expression, Numeric, Numeric, Numeric",
+        "Returns the integral of the expression in x between the bounds, which can be infinite, to the optional tolerance. Written as integrate(expression, from, to)",
+        "integrate(math::exp(-x^2), -1 / 0.0, 1 / 0.0)",
+        Some(calculus::integrate)
+    ),
+    builtin!(
+        "num::quad",
+        "3-4",
+        "Expression, Numeric, Numeric, Numeric",
+        "Returns the integral like integrate and its estimated error, as (integral, error). Written as quad(expression, from, to)",
+        "quad(math::sqrt(x), 0, 1)",
+        Some(calculus::quad)
+    ),
+    builtin!(
+        "num::derivative",
+        "2",
+        "Expression, Numeric",
+        "Returns the derivative of the expression in x at the number. Written as derivative(expression, x)",
+        "derivative(math::sin(x), 0)",
+        Some(calculus::derivative)
+    ),
+    builtin!(
+        "num::limit",
+        "2-3",
+        "Expression, Numeric, Numeric",
+        "Returns the limit of the expression in x at the number, from above if the third argument is 1 or below if it is -1. Written as limit(expression, x)",
+        "limit(math::sin(x) / x, 0)",
+        Some(calculus::limit)
+    ),
+    builtin!(
+        "num::ode",
+        "5",
+        "Expression, Numeric, Numeric, Numeric, Numeric",
+        "Solves y' = f(t, y) from y(t0) = y0 up to t1 in the given number of steps, returning the (t, y) points. Written as ode(expression, y0, t0, t1, steps)",
+        "ode((t, y) -> -2 * t * y, 1, 0, 2, 10)",
+        Some(calculus::ode)
+    ),
+];
+
+const SEQUENCES: &[Builtin] = &[
+    builtin!(
+        "range",
+        "2-3",
+        "Numeric, Numeric, Numeric",
+        "Returns the numbers from the first up to and including the second, in steps of the third or 1. Also written as from..to or from..to step s",
+        "range(0, 1, 0.25)",
+        Some(sequence::range)
+    ),
+    builtin!(
+        "seq::map",
+        "2",
+        "Function, Tuple",
+        "Returns the function applied to each element. Written as map(x -> expression, list), or as the list comprehension [expression for x in list if condition]",
+        "map(x -> x^2, 1..5)",
+        Some(sequence::map)
+    ),
+    builtin!(
+        "seq::filter",
+        "2",
+        "Function, Tuple",
+        "Returns the elements for which the function is true. Written as filter(x -> condition, list)",
+        "filter(x -> x % 2 == 0, 1..10)",
+        Some(sequence::filter)
+    ),
+    builtin!(
+        "seq::reduce",
+        "2-3",
+        "Function, Tuple, Any",
+        "Combines the elements with the function, starting from the optional initial value or the first element. Written as reduce((acc, x) -> expression, list)",
+        "reduce((acc, x) -> acc * x, 1..5)",
+        Some(sequence::reduce)
+    ),
+    builtin!(
+        "seq::sum_over",
+        "4",
+        "Variable, Numeric, Numeric, Expression",
+        "Returns the sum of the expression for the variable going from the first number to the second. Written as sum_over(i, from, to, expression)",
+        "sum_over(i, 1, 100, i^2)",
+        Some(sequence::sum_over)
+    ),
+    builtin!(
+        "seq::product_over",
+        "4",
+        "Variable, Numeric, Numeric, Expression",
+        "Returns the product of the expression for the variable going from the first number to the second. Written as product_over(i, from, to, expression)",
+        "product_over(i, 1, 10, i)",
+        Some(sequence::product_over)
+    ),
+    builtin!(
+        "sort",
+        "1",
+        "Tuple",
+        "Returns the numbers or strings in ascending order",
+        "sort((3, 1, 2))",
+        Some(sequence::sort)
+    ),
+    builtin!(
+        "reverse",
+        "1",
+        "Tuple",
+        "Returns the elements in reverse order",
+        "reverse(1..5)",
+        Some(sequence::reverse)
+    ),
+    builtin!(
+        "zip",
+        ">= 2",
+        "Tuple, Tuple",
+        "Returns tuples of the elements at the same position in each list",
+        "zip((1, 2, 3), (4, 5, 6))",
+        Some(sequence::zip)
+    ),
+];
+
+const CONSTANTS: &[Builtin] = &[
+    constant!("PI", "Archimedes' constant (π)"),
+    constant!("TAU", "The full circle constant (τ = 2π)"),
+    constant!("E", "Euler's number (e)"),
+    constant!("SQRT_2", "√2"),
+    constant!("FRAC_1_SQRT_2", "1/√2"),
+    constant!("FRAC_PI_2", "π/2"),
+    constant!("FRAC_PI_3", "π/3"),
+    constant!("FRAC_PI_4", "π/4"),
+    constant!("FRAC_PI_6", "π/6"),
+    constant!("FRAC_PI_8", "π/8"),
+    constant!("FRAC_1_PI", "1/π"),
+    constant!("FRAC_2_PI", "2/π"),
+    constant!("FRAC_2_SQRT_PI", "2/√π"),
+    constant!("LN_2", "ln(2)"),
+    constant!("LN_10", "ln(10)"),
+    constant!("LOG2_E", "log₂(e)"),
+    constant!("LOG2_10", "log₂(10)"),
+    constant!("LOG10_E", "log₁₀(e)"),
+    constant!("LOG10_2", "log₁₀(2)"),
+];
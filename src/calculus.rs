@@ -0,0 +1,510 @@
+use {
+    crate::{closure::Closure, solver},
+    evalexpr::{EvalexprError, EvalexprResult, Value},
+};
+
+/// Tolerance of integrals when none is given, both absolute and relative to the integral
+const TOLERANCE: f64 = 1e-10;
+/// Most intervals an integral is split into before giving up
+const MAX_INTERVALS: usize = 2000;
+
+/// Nodes of the 15 point Gauss–Kronrod rule on [-1, 1], the positive half, and their weights.
+/// Every other node is also one of the 7 point Gauss rule, with `GAUSS_WEIGHTS`.
+const KRONROD_NODES: [f64; 8] = [
+    0.991_455_371_120_812_6,
+    0.949_107_912_342_758_5,
+    0.864_864_423_359_769_1,
+    0.741_531_185_599_394_4,
+    0.586_087_235_467_691_1,
+    0.405_845_151_377_397_2,
+    0.207_784_955_007_898_5,
+    0.0,
+];
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022_935_322_010_529_22,
+    0.063_092_092_629_978_55,
+    0.104_790_010_322_250_2,
+    0.140_653_259_715_525_9,
+    0.169_004_726_639_267_9,
+    0.190_350_578_064_785_4,
+    0.204_432_940_075_298_9,
+    0.209_482_141_084_727_8,
+];
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.129_484_966_168_869_7,
+    0.279_705_391_489_276_7,
+    0.381_830_050_505_118_9,
+    0.417_959_183_673_469_4,
+];
+
+/// Steps shrink by this factor when extrapolating derivatives and limits
+const SHRINK: f64 = 1.4;
+const EXTRAPOLATION_STEPS: usize = 16;
+
+fn error<T>(message: String) -> EvalexprResult<T> {
+    Err(EvalexprError::CustomMessage(message))
+}
+
+fn number(args: &[Value], function: &str) -> EvalexprResult<f64> {
+    match args {
+        [x] => x.as_number(),
+        _ => error(format!(
+            "Expected an expression and a number, like {}(x^2, 1)",
+            function
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    from: f64,
+    to: f64,
+    value: f64,
+    error: f64,
+}
+
+/// Integrate `f` over a segment with the Gauss–Kronrod rule, its difference to the Gauss rule
+/// estimates the error
+fn gauss_kronrod(
+    f: &mut impl FnMut(f64) -> EvalexprResult<f64>,
+    from: f64,
+    to: f64,
+) -> EvalexprResult<Segment> {
+    let (center, half) = ((from + to) / 2.0, (to - from) / 2.0);
+    let (mut kronrod, mut gauss) = (0.0, 0.0);
+    for (i, (x, w)) in KRONROD_NODES.iter().zip(&KRONROD_WEIGHTS).enumerate() {
+        let y = match *x {
+            x if x == 0.0 => f(center)?,
+            x => f(center - half * x)? + f(center + half * x)?,
+        };
+        kronrod += w * y;
+        if i % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[i / 2] * y;
+        }
+    }
+    Ok(Segment {
+        from,
+        to,
+        value: kronrod * half,
+        error: ((kronrod - gauss) * half).abs(),
+    })
+}
+
+/// Integrate `f` from `from` to `to`, splitting the segment with the largest error estimate
+/// until the total one is within `tolerance`. Returns the integral and its error estimate.
+fn adaptive(
+    f: &mut impl FnMut(f64) -> EvalexprResult<f64>,
+    from: f64,
+    to: f64,
+    tolerance: f64,
+) -> EvalexprResult<(f64, f64)> {
+    let mut segments = vec![gauss_kronrod(f, from, to)?];
+    loop {
+        let value: f64 = segments.iter().map(|s| s.value).sum();
+        let error: f64 = segments.iter().map(|s| s.error).sum();
+        if error <= tolerance * value.abs().max(1.0)
+            || !error.is_finite()
+            || segments.len() >= MAX_INTERVALS
+        {
+            return Ok((value, error));
+        }
+
+        let worst = (0..segments.len())
+            .max_by(|&a, &b| {
+                segments[a]
+                    .error
+                    .partial_cmp(&segments[b].error)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(0);
+        let s = segments.swap_remove(worst);
+        let mid = s.from + (s.to - s.from) / 2.0;
+        if mid <= s.from || mid >= s.to {
+            segments.push(s);
+            return Ok((value, error));
+        }
+        segments.push(gauss_kronrod(f, s.from, mid)?);
+        segments.push(gauss_kronrod(f, mid, s.to)?);
+    }
+}
+
+/// The integral of `closure` from `from` to `to` and its error estimate. Infinite bounds are
+/// mapped to finite ones, with `x = t / (1 - t)` for example.
+fn integral(
+    closure: &mut Closure,
+    from: f64,
+    to: f64,
+    tolerance: f64,
+) -> EvalexprResult<(f64, f64)> {
+    if from.is_nan() || to.is_nan() {
+        return error(String::from(
+            "Expected numbers as the bounds of the integral",
+        ));
+    }
+    if from == to {
+        return Ok((0.0, 0.0));
+    }
+    if from > to {
+        return integral(closure, to, from, tolerance).map(|(value, error)| (-value, error));
+    }
+
+    let mut f = |x: f64| closure.number(&[x]);
+    match (from.is_finite(), to.is_finite()) {
+        (true, true) => adaptive(&mut f, from, to, tolerance),
+        (true, false) => adaptive(
+            &mut |t| Ok(f(from + t / (1.0 - t))? / (1.0 - t).powi(2)),
+            0.0,
+            1.0,
+            tolerance,
+        ),
+        (false, true) => adaptive(
+            &mut |t| Ok(f(to - (1.0 - t) / t)? / t.powi(2)),
+            0.0,
+            1.0,
+            tolerance,
+        ),
+        (false, false) => adaptive(
+            &mut |t| {
+                let s = 1.0 - t * t;
+                Ok(f(t / s)? * (1.0 + t * t) / (s * s))
+            },
+            -1.0,
+            1.0,
+            tolerance,
+        ),
+    }
+}
+
+/// The integral of `integrate` and `quad`, its error estimate and the tolerance it was asked for
+fn integral_args(value: &Value) -> EvalexprResult<(f64, f64, f64)> {
+    let args = value.as_tuple()?;
+    let (mut closure, rest) = Closure::from_args(&args)?;
+    let (from, to, tolerance) = match rest {
+        [from, to] => (from.as_number()?, to.as_number()?, TOLERANCE),
+        [from, to, tolerance] => (from.as_number()?, to.as_number()?, tolerance.as_number()?),
+        _ => {
+            return error(String::from(
+                "Expected an expression, the bounds and optionally a tolerance",
+            ))
+        }
+    };
+    if !(tolerance > 0.0) {
+        return error(format!("Expected a positive tolerance, got {}", tolerance));
+    }
+    let (value, estimate) = integral(&mut closure, from, to, tolerance)?;
+    if !value.is_finite() || !estimate.is_finite() {
+        return error(format!(
+            "The integral from {} to {} does not converge",
+            from, to
+        ));
+    }
+    Ok((value, estimate, tolerance))
+}
+
+/// `num::integrate(f, from, to, [tolerance])`, the integral of `f`, an expression in `x`, by
+/// adaptive Gauss–Kronrod quadrature. The bounds can be infinite, `1 / 0.0`.
+pub fn integrate(value: &Value) -> EvalexprResult<Value> {
+    let (value, estimate, tolerance) = integral_args(value)?;
+    if estimate > tolerance * value.abs().max(1.0) {
+        return error(format!(
+            "The integral did not reach the tolerance, it is {} with an estimated error of {}",
+            value, estimate
+        ));
+    }
+    Ok(Value::Float(value))
+}
+
+/// `num::quad(f, from, to, [tolerance])`, the integral like `integrate` and its error estimate,
+/// as `(integral, error)`, even when the estimate is above the tolerance
+pub fn quad(value: &Value) -> EvalexprResult<Value> {
+    let (value, estimate, _) = integral_args(value)?;
+    Ok(Value::Tuple(vec![
+        Value::Float(value),
+        Value::Float(estimate),
+    ]))
+}
+
+/// Richardson extrapolation of `g(h)` to `h = 0` from `h` and smaller steps, for `g` with an
+/// error in powers of `h^order`. Returns the extrapolation with the smallest error estimate,
+/// that estimate and the values of `g`.
+fn extrapolate(
+    g: &mut impl FnMut(f64) -> EvalexprResult<f64>,
+    mut h: f64,
+    order: i32,
+) -> EvalexprResult<(f64, f64, Vec<f64>)> {
+    let ratio = SHRINK.powi(order);
+    let mut table = vec![vec![g(h)?]];
+    let mut best = (table[0][0], f64::INFINITY);
+    for i in 1..EXTRAPOLATION_STEPS {
+        h /= SHRINK;
+        let mut row = vec![g(h)?];
+        let mut factor = ratio;
+        for j in 1..=i {
+            let next = (row[j - 1] * factor - table[i - 1][j - 1]) / (factor - 1.0);
+            let error = (next - row[j - 1])
+                .abs()
+                .max((next - table[i - 1][j - 1]).abs());
+            if error <= best.1 {
+                best = (next, error);
+            }
+            row.push(next);
+            factor *= ratio;
+        }
+        // Rounding errors have taken over when higher orders get worse
+        let worse = (row[i] - table[i - 1][i - 1]).abs() >= 2.0 * best.1;
+        table.push(row);
+        if worse {
+            break;
+        }
+    }
+    Ok((best.0, best.1, table.iter().map(|row| row[0]).collect()))
+}
+
+/// `num::derivative(f, x)`, the derivative of `f`, an expression in `x`, by Ridders' method:
+/// central differences with shrinking steps, extrapolated to a step of 0
+pub fn derivative(value: &Value) -> EvalexprResult<Value> {
+    let args = value.as_tuple()?;
+    let (mut closure, rest) = Closure::from_args(&args)?;
+    let x = number(rest, "derivative")?;
+    let mut f = |x: f64| closure.number(&[x]);
+    let (slope, error, _) = extrapolate(
+        &mut |h| Ok((f(x + h)? - f(x - h)?) / (2.0 * h)),
+        0.1 * x.abs().max(1.0),
+        2,
+    )?;
+    Ok(Value::Float(solver::tidy(slope, error)))
+}
+
+/// Aitken's extrapolation of `g(h)` to `h = 0` from steps shrinking tenfold, for limits like
+/// the one of `sqrt(x)` at 0 that are not smooth enough for Richardson extrapolation. Returns
+/// the limit and an estimate of its error.
+fn aitken(g: &mut impl FnMut(f64) -> EvalexprResult<f64>, h: f64) -> Option<(f64, f64)> {
+    let values = (0..16)
+        .map(|k| g(h / 10f64.powi(k)))
+        .collect::<EvalexprResult<Vec<_>>>()
+        .ok()?;
+    let estimates = values
+        .windows(3)
+        .map(|w| {
+            let (d1, d2) = (w[1] - w[0], w[2] - w[1]);
+            match d2 - d1 {
+                dd if dd == 0.0 => w[2],
+                dd => w[2] - d2 * d2 / dd,
+            }
+        })
+        .collect::<Vec<_>>();
+    let last = &estimates[estimates.len() - 3..];
+    let limit = last[2];
+    let error = last.iter().map(|e| (e - limit).abs()).fold(0.0, f64::max);
+    match error <= 1e-6 * limit.abs().max(1.0) {
+        true => Some((limit, error)),
+        false => None,
+    }
+}
+
+/// The limit of `g(h)` as `h` goes to 0 from above, infinite when `g` grows without slowing
+/// down, and an estimate of its error
+fn limit_at_zero(g: &mut impl FnMut(f64) -> EvalexprResult<f64>, h: f64) -> Option<(f64, f64)> {
+    let (value, error, values) = extrapolate(g, h, 1).ok()?;
+    if error <= 1e-6 * value.abs().max(1.0) {
+        return Some((value, error));
+    }
+    let steps = values
+        .windows(2)
+        .map(|pair| pair[1].abs() - pair[0].abs())
+        .collect::<Vec<_>>();
+    let growing = values.iter().all(|v| v.signum() == values[0].signum())
+        && steps.iter().all(|s| *s > 0.0)
+        && steps.last()? >= &(0.9 * steps[0]);
+    match growing {
+        true => Some((f64::INFINITY.copysign(values[0]), 0.0)),
+        false => aitken(g, h),
+    }
+}
+
+/// `num::limit(f, x, [side])`, the limit of `f`, an expression in `x`, at `x`. `side` is 1 for
+/// the limit from above, -1 from below and 0, the default, for both. `x` can be infinite.
+pub fn limit(value: &Value) -> EvalexprResult<Value> {
+    let args = value.as_tuple()?;
+    let (mut closure, rest) = Closure::from_args(&args)?;
+    let (x, side) = match rest {
+        [x] => (x.as_number()?, 0.0),
+        [x, side] => (x.as_number()?, side.as_number()?.signum()),
+        _ => {
+            return error(String::from(
+                "Expected an expression, a number and optionally the side to approach it from",
+            ))
+        }
+    };
+    let mut f = |x: f64| closure.number(&[x]);
+
+    if x.is_infinite() {
+        return match limit_at_zero(&mut |h| f(x.signum() / h), 0.1) {
+            Some((limit, error)) => Ok(Value::Float(solver::tidy(limit, error))),
+            None => error(format!("The limit at {} does not exist", x)),
+        };
+    }
+    let h = 0.1 * x.abs().max(1.0);
+    let below = match side {
+        s if s > 0.0 => None,
+        _ => limit_at_zero(&mut |h| f(x - h), h),
+    };
+    let above = match side {
+        s if s < 0.0 => None,
+        _ => limit_at_zero(&mut |h| f(x + h), h),
+    };
+    match (below, above) {
+        (Some((b, below)), Some((a, above)))
+            if a == b || (a.is_finite() && (a - b).abs() <= 1e-6 * a.abs().max(1.0)) =>
+        {
+            let error = below.max(above).max((a - b).abs() / 2.0);
+            Ok(Value::Float(solver::tidy((a + b) / 2.0, error)))
+        }
+        (Some((b, _)), Some((a, _))) => error(format!(
+            "The limits from below, {}, and from above, {}, differ",
+            b, a
+        )),
+        (Some((limit, error)), None) | (None, Some((limit, error))) => {
+            Ok(Value::Float(solver::tidy(limit, error)))
+        }
+        (None, None) => error(format!("The limit at {} does not exist", x)),
+    }
+}
+
+/// `num::ode(f, y0, t0, t1, steps)`, the solution of `y' = f(t, y)` with `y(t0) = y0` by the
+/// Runge–Kutta method, as a tuple of `(t, y)` points from `t0` to `t1`
+pub fn ode(value: &Value) -> EvalexprResult<Value> {
+    let args = value.as_tuple()?;
+    let (mut closure, rest) = Closure::from_args(&args)?;
+    let (y, t0, t1, steps) = match rest {
+        [y0, t0, t1, steps] => (
+            y0.as_number()?,
+            t0.as_number()?,
+            t1.as_number()?,
+            steps.as_number()?,
+        ),
+        _ => {
+            return error(String::from(
+                "Expected an expression in t and y, y0, t0, t1 and the number of steps",
+            ))
+        }
+    };
+    if steps < 1.0 || steps.fract() != 0.0 || steps > 100_000.0 {
+        return error(format!(
+            "Expected a whole number of steps up to 100000, got {}",
+            steps
+        ));
+    }
+
+    let points = runge_kutta(
+        &mut |t, y| closure.number(&[t, y]),
+        y,
+        t0,
+        t1,
+        steps as usize,
+    )?;
+    Ok(Value::Tuple(
+        points
+            .into_iter()
+            .map(|(t, y)| Value::Tuple(vec![Value::Float(t), Value::Float(y)]))
+            .collect(),
+    ))
+}
+
+/// The `(t, y)` points of the solution of `y' = f(t, y)` with `y(t0) = y`, in `steps` steps of
+/// the classic fourth order Runge–Kutta method
+fn runge_kutta(
+    f: &mut impl FnMut(f64, f64) -> EvalexprResult<f64>,
+    mut y: f64,
+    t0: f64,
+    t1: f64,
+    steps: usize,
+) -> EvalexprResult<Vec<(f64, f64)>> {
+    let h = (t1 - t0) / steps as f64;
+    let mut points = vec![(t0, y)];
+    for i in 0..steps {
+        let t = t0 + h * i as f64;
+        let k1 = f(t, y)?;
+        let k2 = f(t + h / 2.0, y + h / 2.0 * k1)?;
+        let k3 = f(t + h / 2.0, y + h / 2.0 * k2)?;
+        let k4 = f(t + h, y + h * k3)?;
+        y += h / 6.0 * (k1 + 2.0 * k2 + 2.0 * k3 + k4);
+        points.push((t0 + h * (i + 1) as f64, y));
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn integrate(mut f: impl FnMut(f64) -> f64, from: f64, to: f64) -> f64 {
+        adaptive(&mut |x| Ok(f(x)), from, to, TOLERANCE).unwrap().0
+    }
+
+    #[test]
+    fn integrals() {
+        assert_close(integrate(|x| x * x, 0.0, 1.0), 1.0 / 3.0, 1e-12);
+        assert_close(integrate(f64::exp, 0.0, 1.0), 1f64.exp() - 1.0, 1e-12);
+        assert_close(integrate(f64::sin, 0.0, std::f64::consts::PI), 2.0, 1e-12);
+        // Not smooth at 0
+        assert_close(integrate(f64::sqrt, 0.0, 1.0), 2.0 / 3.0, 1e-9);
+        assert_close(
+            integrate(|x| 1.0 / (1.0 + x * x), -1e3, 1e3),
+            2.0 * 1e3f64.atan(),
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn derivatives() {
+        let (slope, error, _) = extrapolate(
+            &mut |h| Ok(((1.0 + h).sin() - (1.0 - h).sin()) / (2.0 * h)),
+            0.1,
+            2,
+        )
+        .unwrap();
+        assert_close(slope, 1f64.cos(), 1e-12);
+        assert!(error < 1e-10);
+    }
+
+    #[test]
+    fn limits() {
+        let limit = |mut g: Box<dyn FnMut(f64) -> f64>| {
+            limit_at_zero(&mut |h| Ok(g(h)), 0.1).map(|(limit, _)| limit)
+        };
+        assert_close(limit(Box::new(|h| h.sin() / h)).unwrap(), 1.0, 1e-9);
+        assert_close(
+            limit(Box::new(|h| (1.0 + h).powf(1.0 / h))).unwrap(),
+            std::f64::consts::E,
+            1e-9,
+        );
+        assert_close(limit(Box::new(|h| h.sqrt())).unwrap(), 0.0, 1e-6);
+        assert_eq!(limit(Box::new(|h| 1.0 / h)), Some(f64::INFINITY));
+        assert_eq!(limit(Box::new(|h| -1.0 / h)), Some(f64::NEG_INFINITY));
+        assert_eq!(limit(Box::new(|h| (1.0 / h).sin())), None);
+    }
+
+    #[test]
+    fn differential_equations() {
+        // y' = y, y(0) = 1 is e^t
+        let points = runge_kutta(&mut |_, y| Ok(y), 1.0, 0.0, 1.0, 100).unwrap();
+        assert_eq!(points.len(), 101);
+        let (t, y) = points[100];
+        assert_close(t, 1.0, 1e-12);
+        assert_close(y, std::f64::consts::E, 1e-9);
+
+        // y' = -2ty, y(0) = 1 is e^(-t^2)
+        let points = runge_kutta(&mut |t, y| Ok(-2.0 * t * y), 1.0, 0.0, 2.0, 200).unwrap();
+        assert_close(points[200].1, (-4f64).exp(), 1e-8);
+    }
+}
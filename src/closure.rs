@@ -0,0 +1,108 @@
+use {
+    crate::eval,
+    evalexpr::{
+        ContextWithMutableVariables, EvalexprError, EvalexprResult, HashMapContext, Node, Value,
+    },
+};
+
+/// An expression over variables of its own, given to a builtin as three arguments: its text,
+/// the names of its variables and the values of the other variables it uses. The preprocessor
+/// writes these, see `preprocess::closure_calls`.
+pub struct Closure {
+    params: Vec<String>,
+    node: Node,
+    bindings: Vec<(String, Value)>,
+    context: HashMapContext,
+}
+
+/// A fresh context with `bindings` set
+fn context(bindings: &[(String, Value)]) -> EvalexprResult<HashMapContext> {
+    let mut context = eval::current_context();
+    for (name, value) in bindings {
+        context.set_value(name.clone(), value.clone())?;
+    }
+    Ok(context)
+}
+
+impl Closure {
+    /// The closure in the first three of `args`, and the arguments after it
+    pub fn from_args(args: &[Value]) -> EvalexprResult<(Self, &[Value])> {
+        let (body, params, bindings, rest) = match args {
+            [body, params, bindings, rest @ ..] => (body, params, bindings, rest),
+            _ => {
+                return Err(EvalexprError::WrongFunctionArgumentAmount {
+                    expected: 3,
+                    actual: args.len(),
+                })
+            }
+        };
+
+        let params = match params {
+            Value::Tuple(t) => t
+                .iter()
+                .map(Value::as_string)
+                .collect::<EvalexprResult<_>>()?,
+            Value::Empty => Vec::new(),
+            p => vec![p.as_string()?],
+        };
+        if params.is_empty() {
+            return Err(EvalexprError::CustomMessage(String::from(
+                "Expected the variables of the expression",
+            )));
+        }
+
+        let bindings = match bindings {
+            Value::Tuple(pairs) => pairs
+                .chunks(2)
+                .filter_map(|pair| match pair {
+                    [name, value] => Some(name.as_string().map(|name| (name, value.clone()))),
+                    _ => None,
+                })
+                .collect::<EvalexprResult<_>>()?,
+            _ => Vec::new(),
+        };
+        let node = evalexpr::build_operator_tree(&body.as_string()?)?;
+        Ok((
+            Self {
+                params,
+                node,
+                context: context(&bindings)?,
+                bindings,
+            },
+            rest,
+        ))
+    }
+
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// Evaluate the expression with its variables set to `args`
+    pub fn call(&mut self, args: &[Value]) -> EvalexprResult<Value> {
+        if args.len() != self.params.len() {
+            return Err(EvalexprError::WrongFunctionArgumentAmount {
+                expected: self.params.len(),
+                actual: args.len(),
+            });
+        }
+        let params = &self.params;
+        let set = |context: &mut HashMapContext| {
+            params
+                .iter()
+                .zip(args)
+                .try_for_each(|(name, value)| context.set_value(name.clone(), value.clone()))
+        };
+        // Variables keep the type of their first value, start over for values of another one
+        if set(&mut self.context).is_err() {
+            self.context = context(&self.bindings)?;
+            set(&mut self.context)?;
+        }
+        self.node.eval_with_context(&self.context)
+    }
+
+    /// `call` with numbers, for expressions of a single number
+    pub fn number(&mut self, args: &[f64]) -> EvalexprResult<f64> {
+        let args = args.iter().map(|a| Value::Float(*a)).collect::<Vec<_>>();
+        self.call(&args)?.as_number()
+    }
+}
@@ -0,0 +1,420 @@
+use {
+    evalexpr::{EvalexprError, EvalexprResult, Value},
+    std::{
+        cell::RefCell,
+        collections::HashMap,
+        fs,
+        path::{Path, PathBuf},
+        rc::Rc,
+        time::SystemTime,
+    },
+};
+
+const FILE_NAMES: &[&str] = &["rates.csv", "rates.json"];
+
+thread_local! {
+    /// Rates file given on the command line, used instead of the one in the config dir
+    static PATH: RefCell<Option<PathBuf>> = RefCell::new(None);
+    /// Last loaded rates, reloaded when the file changes
+    static CACHE: RefCell<Option<(PathBuf, SystemTime, Rc<Rates>)>> = RefCell::new(None);
+}
+
+pub fn dir() -> PathBuf {
+    glib::get_user_config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("math-expr-eval")
+}
+
+pub fn set_path(path: PathBuf) {
+    PATH.with(|p| p.replace(Some(path)));
+}
+
+/// The rates file in use, if there is one
+pub fn path() -> Option<PathBuf> {
+    PATH.with(|p| p.borrow().clone()).or_else(|| {
+        FILE_NAMES
+            .iter()
+            .map(|name| dir().join(name))
+            .find(|p| p.is_file())
+    })
+}
+
+/// Exchange rates, in units of each currency per unit of the base currency. Written as CSV,
+///
+/// ```text
+/// date,2024-05-01
+/// base,EUR
+/// USD,1.0812
+/// GBP,0.8571
+/// ```
+///
+/// or as JSON, `{"date": "2024-05-01", "base": "EUR", "rates": {"USD": 1.0812, "GBP": 0.8571}}`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rates {
+    pub date: Option<String>,
+    pub base: Option<String>,
+    pub rates: HashMap<String, f64>,
+}
+
+impl Rates {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let json = path.extension().map(|e| e == "json").unwrap_or(false);
+        match json {
+            true => Self::from_json(&text),
+            false => Self::from_csv(&text),
+        }
+        .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_csv(text: &str) -> Result<Self, String> {
+        let mut rates = Self::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(2, ',').map(|f| f.trim().trim_matches('"'));
+            match (fields.next(), fields.next()) {
+                (Some("date"), Some(date)) => rates.date = Some(date.to_string()),
+                (Some("base"), Some(base)) => rates.base = Some(base.to_uppercase()),
+                (Some(code), Some(rate)) => {
+                    let rate = rate
+                        .parse()
+                        .map_err(|_| format!("line {}: invalid rate \"{}\"", n + 1, rate))?;
+                    rates
+                        .add(code, rate)
+                        .map_err(|e| format!("line {}: {}", n + 1, e))?;
+                }
+                _ => return Err(format!("line {}: expected CODE,rate", n + 1)),
+            }
+        }
+        Ok(rates)
+    }
+
+    fn from_json(text: &str) -> Result<Self, String> {
+        let mut parser = Json { text, pos: 0 };
+        let fields = match parser.value()? {
+            JsonValue::Object(fields) => fields,
+            _ => return Err(String::from("expected an object")),
+        };
+
+        let mut rates = Self::default();
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("date", JsonValue::String(date)) => rates.date = Some(date),
+                ("base", JsonValue::String(base)) => rates.base = Some(base.to_uppercase()),
+                ("rates", JsonValue::Object(codes)) => {
+                    for (code, rate) in codes {
+                        match rate {
+                            JsonValue::Number(rate) => rates.add(&code, rate)?,
+                            _ => return Err(format!("the rate of {} is not a number", code)),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(rates)
+    }
+
+    /// Amounts are divided and multiplied by rates, so only positive ones are taken
+    fn add(&mut self, code: &str, rate: f64) -> Result<(), String> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(format!("the rate of {} is not a positive number", code));
+        }
+        self.rates.insert(code.to_uppercase(), rate);
+        Ok(())
+    }
+
+    /// Units of `code` per unit of the base currency
+    pub fn rate(&self, code: &str) -> Option<f64> {
+        match self.base.as_deref() {
+            Some(base) if base == code => Some(1.0),
+            _ => self.rates.get(code).copied(),
+        }
+    }
+}
+
+/// The rates of the rates file in use
+pub fn rates() -> Result<Rc<Rates>, String> {
+    let path = path().ok_or_else(|| {
+        format!(
+            "No currency rates, add {} to {}",
+            FILE_NAMES.join(" or "),
+            dir().display()
+        )
+    })?;
+    let modified = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    CACHE.with(|cache| {
+        if let Some((p, m, rates)) = &*cache.borrow() {
+            if *p == path && *m == modified {
+                return Ok(rates.clone());
+            }
+        }
+        let rates = Rc::new(Rates::load(&path)?);
+        cache.replace(Some((path, modified, rates.clone())));
+        Ok(rates)
+    })
+}
+
+/// Whether the rates file in use has a rate for `code`
+pub fn is_known(code: &str) -> bool {
+    rates().map_or(false, |rates| rates.rate(code).is_some())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue {
+    Object(Vec<(String, JsonValue)>),
+    String(String),
+    Number(f64),
+    /// Arrays, booleans and null, which rates files have no use for
+    Other,
+}
+
+/// Just enough JSON for rates files
+struct Json<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Json<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        self.pos = self.text.len() - self.rest().trim_start().len();
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_space();
+        match self.rest().starts_with(c) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(format!("expected '{}' at byte {}", c, self.pos)),
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        self.skip_space();
+        match self.rest().chars().next() {
+            Some('{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_space();
+                if !self.rest().starts_with('}') {
+                    loop {
+                        self.skip_space();
+                        let key = self.string()?;
+                        self.expect(':')?;
+                        fields.push((key, self.value()?));
+                        self.skip_space();
+                        if !self.rest().starts_with(',') {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                self.expect('}')?;
+                Ok(JsonValue::Object(fields))
+            }
+            Some('[') => {
+                self.pos += 1;
+                self.skip_space();
+                if !self.rest().starts_with(']') {
+                    loop {
+                        self.value()?;
+                        self.skip_space();
+                        if !self.rest().starts_with(',') {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                }
+                self.expect(']')?;
+                Ok(JsonValue::Other)
+            }
+            Some('"') => self.string().map(JsonValue::String),
+            Some(_) => {
+                let len = self
+                    .rest()
+                    .find(|c: char| c == ',' || c == '}' || c == ']' || c.is_whitespace())
+                    .unwrap_or_else(|| self.rest().len());
+                let word = &self.rest()[..len];
+                self.pos += len;
+                match word {
+                    "true" | "false" | "null" => Ok(JsonValue::Other),
+                    n => n
+                        .parse()
+                        .map(JsonValue::Number)
+                        .map_err(|_| format!("unexpected \"{}\"", n)),
+                }
+            }
+            None => Err(String::from("unexpected end of file")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        while let Some(c) = self.rest().chars().next() {
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => out.push(self.escape()?),
+                c => out.push(c),
+            }
+        }
+        Err(String::from("unterminated string"))
+    }
+
+    /// The character written by the escape after a backslash
+    fn escape(&mut self) -> Result<char, String> {
+        let start = self.pos - 1;
+        let invalid = || format!("invalid escape at byte {}", start);
+        let c = self.rest().chars().next().ok_or_else(invalid)?;
+        self.pos += c.len_utf8();
+        Ok(match c {
+            '"' | '\\' | '/' => c,
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.code_unit().ok_or_else(invalid)?;
+                // Characters past U+FFFF are written as a pair of UTF-16 surrogates
+                let code = match high {
+                    0xD800..=0xDBFF if self.rest().starts_with("\\u") => {
+                        self.pos += 2;
+                        match self.code_unit().ok_or_else(invalid)? {
+                            low @ 0xDC00..=0xDFFF => {
+                                0x10000 + (high - 0xD800) * 0x400 + low - 0xDC00
+                            }
+                            _ => return Err(invalid()),
+                        }
+                    }
+                    _ => high,
+                };
+                std::char::from_u32(code).ok_or_else(invalid)?
+            }
+            _ => return Err(invalid()),
+        })
+    }
+
+    /// The four hex digits of a `\u` escape
+    fn code_unit(&mut self) -> Option<u32> {
+        let hex = self.rest().get(..4)?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.pos += 4;
+        u32::from_str_radix(hex, 16).ok()
+    }
+}
+
+fn error<T>(message: String) -> EvalexprResult<T> {
+    Err(EvalexprError::CustomMessage(message))
+}
+
+fn rate(rates: &Rates, code: &str) -> EvalexprResult<f64> {
+    match rates.rate(&code.to_uppercase()) {
+        Some(rate) => Ok(rate),
+        None => error(format!("No rate for {} in the currency rates", code)),
+    }
+}
+
+/// `(amount, code)` arguments
+fn amount_and_code(value: &Value) -> EvalexprResult<(f64, String)> {
+    match value.as_tuple()?.as_slice() {
+        [amount, code] => Ok((amount.as_number()?, code.as_string()?)),
+        args => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 2,
+            actual: args.len(),
+        }),
+    }
+}
+
+/// `cur::from(amount, code)`, `amount` of `code` in the base currency. `100 USD` is rewritten
+/// into this.
+pub fn from(value: &Value) -> EvalexprResult<Value> {
+    let (amount, code) = amount_and_code(value)?;
+    let rates = rates().or_else(error)?;
+    Ok(Value::Float(amount / rate(&rates, &code)?))
+}
+
+/// `cur::to(amount, code)`, an amount in the base currency in `code`. `x in EUR` and
+/// `x to EUR` are rewritten into this.
+pub fn to(value: &Value) -> EvalexprResult<Value> {
+    let (amount, code) = amount_and_code(value)?;
+    let rates = rates().or_else(error)?;
+    Ok(Value::Float(amount * rate(&rates, &code)?))
+}
+
+/// `cur::convert(amount, from, to)`
+pub fn convert(value: &Value) -> EvalexprResult<Value> {
+    match value.as_tuple()?.as_slice() {
+        [amount, from, to] => {
+            let rates = rates().or_else(error)?;
+            Ok(Value::Float(
+                amount.as_number()? / rate(&rates, &from.as_string()?)?
+                    * rate(&rates, &to.as_string()?)?,
+            ))
+        }
+        args => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 3,
+            actual: args.len(),
+        }),
+    }
+}
+
+/// `cur::rate(code)`, units of `code` per unit of the base currency
+pub fn exchange_rate(value: &Value) -> EvalexprResult<Value> {
+    let rates = rates().or_else(error)?;
+    rate(&rates, &value.as_string()?).map(Value::Float)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_and_json() {
+        let csv = Rates::from_csv("date,2024-05-01\nbase,eur\n# comment\nUSD,1.0812\n").unwrap();
+        let json =
+            Rates::from_json(r#"{"date": "2024-05-01", "base": "EUR", "rates": {"usd": 1.0812}}"#)
+                .unwrap();
+        assert_eq!(csv, json);
+        assert_eq!(csv.rate("EUR"), Some(1.0));
+        assert_eq!(csv.rate("USD"), Some(1.0812));
+        assert_eq!(csv.rate("GBP"), None);
+    }
+
+    #[test]
+    fn rates_must_be_positive() {
+        assert!(Rates::from_csv("USD,0").is_err());
+        assert!(Rates::from_csv("USD,-1.08").is_err());
+        assert!(Rates::from_csv("USD,inf").is_err());
+        assert!(Rates::from_csv("USD,NaN").is_err());
+        assert!(Rates::from_json(r#"{"rates": {"USD": 0}}"#).is_err());
+        assert!(Rates::from_json(r#"{"rates": {"USD": NaN}}"#).is_err());
+    }
+
+    #[test]
+    fn json_escapes() {
+        let date =
+            |text: &str| Rates::from_json(&format!(r#"{{"date": "{}"}}"#, text)).map(|r| r.date);
+        assert_eq!(date(r#"a\"b\\c\/d"#), Ok(Some(String::from("a\"b\\c/d"))));
+        assert_eq!(date(r#"\u00e9\u20AC"#), Ok(Some(String::from("é€"))));
+        assert_eq!(date(r#"\ud83d\udcb6"#), Ok(Some(String::from("💶"))));
+        assert!(date(r#"\ud83d"#).is_err());
+        assert!(date(r#"\u12"#).is_err());
+        assert!(date(r#"\x"#).is_err());
+    }
+}
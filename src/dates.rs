@@ -0,0 +1,385 @@
+use {
+    evalexpr::{EvalexprError, EvalexprResult, Value},
+    glib::{DateTime, TimeZone},
+    std::{env, path::PathBuf},
+};
+
+fn error<T>(message: String) -> EvalexprResult<T> {
+    Err(EvalexprError::CustomMessage(message))
+}
+
+fn arguments(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Tuple(t) => t.clone(),
+        Value::Empty => Vec::new(),
+        v => vec![v.clone()],
+    }
+}
+
+/// Arguments of a function taking between `min` and `max` of them
+fn arguments_between(value: &Value, min: usize, max: usize) -> EvalexprResult<Vec<Value>> {
+    let args = arguments(value);
+    if args.len() < min || args.len() > max {
+        return Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: min,
+            actual: args.len(),
+        });
+    }
+    Ok(args)
+}
+
+/// Dates are seconds since 1970-01-01 00:00 UTC and durations are seconds, so they work with
+/// the usual operators: `date::parse("2024-01-31") + 12 hours`
+fn timestamp(value: &Value) -> EvalexprResult<i64> {
+    let seconds = value.as_number()?.round();
+    // `as` saturates, so check what fits first
+    if seconds.is_finite() && seconds.abs() < i64::MAX as f64 {
+        Ok(seconds as i64)
+    } else {
+        error(format!("Expected a number of seconds, got {}", seconds))
+    }
+}
+
+/// The time zone named by `name` in the tz database, or the local one
+fn time_zone(name: Option<&Value>) -> EvalexprResult<TimeZone> {
+    let name = match name {
+        Some(v) => v.as_string()?,
+        None => return Ok(TimeZone::new_local()),
+    };
+    let dir = env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/zoneinfo"));
+    // GLib falls back to UTC for unknown names, check they exist instead
+    if name == "UTC" || dir.join(&name).is_file() {
+        Ok(TimeZone::new(Some(&name)))
+    } else {
+        error(format!("Unknown time zone \"{}\"", name))
+    }
+}
+
+/// Seconds of 0001-01-01 00:00:00 and 9999-12-31 23:59:59 UTC, the range GLib supports
+const SUPPORTED: std::ops::RangeInclusive<i64> = -62_135_596_800..=253_402_300_799;
+
+fn local(time: i64, tz: &TimeZone) -> EvalexprResult<DateTime> {
+    // GLib aborts on times outside of its range rather than failing
+    let date = if SUPPORTED.contains(&time) {
+        DateTime::from_unix_utc(time).to_timezone(tz)
+    } else {
+        None
+    };
+    match date {
+        Some(d) => Ok(d),
+        None => error(format!("{} is out of the supported date range", time)),
+    }
+}
+
+fn days_in_month(year: i32, month: i32) -> i32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i32, month: i32, day: i32) -> i64 {
+    let year = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Days since 1970-01-01 of the calendar day `time` falls on in `tz`
+fn day_number(time: i64, tz: &TimeZone) -> EvalexprResult<i64> {
+    let (y, m, d) = local(time, tz)?.get_ymd();
+    Ok(days_from_civil(y, m, d))
+}
+
+/// Monday to Friday days from the Monday 1969-12-29 up to, not including, `day`
+fn weekdays_before(day: i64) -> i64 {
+    let since_monday = day + 3;
+    since_monday.div_euclid(7) * 5 + since_monday.rem_euclid(7).min(5)
+}
+
+fn is_weekday(day: i64) -> bool {
+    (day + 3).rem_euclid(7) < 5
+}
+
+/// Parse `YYYY-MM-DD`, optionally followed by `HH:MM` or `HH:MM:SS` after a space or `T`
+fn parse_local(text: &str, tz: &TimeZone) -> EvalexprResult<i64> {
+    let invalid = || {
+        error(format!(
+            "Expected a date like 2024-01-31 14:30, got \"{}\"",
+            text
+        ))
+    };
+    let text = text.trim();
+    let (date, time) = match text.find(|c: char| c == ' ' || c == 'T') {
+        Some(i) => (&text[..i], text[i + 1..].trim()),
+        None => (text, ""),
+    };
+
+    let parse = |part: &str, separator: char| {
+        part.split(separator)
+            .map(|n| n.parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+    };
+    let (year, month, day) = match parse(date, '-').as_deref() {
+        Some(&[y, m, d]) => (y, m, d),
+        _ => return invalid(),
+    };
+    let (hour, minute, second) = match parse(time, ':').as_deref() {
+        _ if time.is_empty() => (0, 0, 0),
+        Some(&[h, m]) => (h, m, 0),
+        Some(&[h, m, s]) => (h, m, s),
+        _ => return invalid(),
+    };
+
+    if !(1..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..60).contains(&second)
+    {
+        return invalid();
+    }
+    Ok(DateTime::new(tz, year, month, day, hour, minute, second as f64).to_unix())
+}
+
+/// `date::parse(text, [tz])`
+pub fn parse(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 1, 2)?;
+    let tz = time_zone(args.get(1))?;
+    parse_local(&args[0].as_string()?, &tz).map(Value::Int)
+}
+
+/// `date::now()`
+pub fn now(value: &Value) -> EvalexprResult<Value> {
+    arguments_between(value, 0, 0)?;
+    Ok(Value::Int(DateTime::new_now_utc().to_unix()))
+}
+
+/// `date::today([tz])`, midnight at the start of the current day
+pub fn today(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 0, 1)?;
+    let tz = time_zone(args.get(0))?;
+    let (y, m, d) = DateTime::new_now(&tz).get_ymd();
+    Ok(Value::Int(DateTime::new(&tz, y, m, d, 0, 0, 0.0).to_unix()))
+}
+
+fn format_local(date: &DateTime, format: Option<String>) -> EvalexprResult<String> {
+    let format = match format {
+        Some(f) => f,
+        None if (date.get_hour(), date.get_minute(), date.get_second()) == (0, 0, 0) => {
+            String::from("%Y-%m-%d")
+        }
+        None => String::from("%Y-%m-%d %H:%M:%S"),
+    };
+    match date.format(&format) {
+        Some(s) => Ok(s.to_string()),
+        None => error(format!("Invalid date format \"{}\"", format)),
+    }
+}
+
+/// How results known to be dates are shown, in the local time zone
+pub fn display_date(value: &Value) -> EvalexprResult<String> {
+    format_local(&local(timestamp(value)?, &TimeZone::new_local())?, None)
+}
+
+/// `date::format(date, [format], [tz])`, with `strftime` like formats
+pub fn format(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 1, 3)?;
+    let tz = time_zone(args.get(2))?;
+    let date = local(timestamp(&args[0])?, &tz)?;
+    let format = args.get(1).map(Value::as_string).transpose()?;
+    format_local(&date, format).map(Value::String)
+}
+
+/// A part of a date in the local or given time zone: `f(date, [tz])`
+fn part(value: &Value, get: fn(&DateTime) -> i32) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 1, 2)?;
+    let tz = time_zone(args.get(1))?;
+    Ok(Value::Int(get(&local(timestamp(&args[0])?, &tz)?) as i64))
+}
+
+pub fn year(value: &Value) -> EvalexprResult<Value> {
+    part(value, DateTime::get_year)
+}
+
+pub fn month(value: &Value) -> EvalexprResult<Value> {
+    part(value, DateTime::get_month)
+}
+
+pub fn day(value: &Value) -> EvalexprResult<Value> {
+    part(value, DateTime::get_day_of_month)
+}
+
+/// 1 for Monday to 7 for Sunday
+pub fn weekday(value: &Value) -> EvalexprResult<Value> {
+    part(value, DateTime::get_day_of_week)
+}
+
+/// `date::add_months(date, months, [tz])`, the day is clamped to the length of the month
+pub fn add_months(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 2, 3)?;
+    let tz = time_zone(args.get(2))?;
+    let months = timestamp(&args[1])?;
+    if months.abs() > 120_000 {
+        return error(format!("Can not add {} months", months));
+    }
+    match local(timestamp(&args[0])?, &tz)?.add_months(months as i32) {
+        Some(d) => Ok(Value::Int(d.to_unix())),
+        None => error(format!("Can not add {} months", months)),
+    }
+}
+
+/// `date::add_days(date, days, [tz])`, in calendar days, so the time of day stays the same
+/// across daylight saving changes. `date + 30 days` is rewritten into it.
+pub fn add_days(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 2, 3)?;
+    let tz = time_zone(args.get(2))?;
+    let days = timestamp(&args[1])?;
+    if days.abs() > 4_000_000 {
+        return error(format!("Can not add {} days", days));
+    }
+    match local(timestamp(&args[0])?, &tz)?.add_days(days as i32) {
+        Some(d) => Ok(Value::Int(d.to_unix())),
+        None => error(format!("Can not add {} days", days)),
+    }
+}
+
+/// `date::days_between(from, to, [tz])`, in calendar days
+pub fn days_between(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 2, 3)?;
+    let tz = time_zone(args.get(2))?;
+    let from = day_number(timestamp(&args[0])?, &tz)?;
+    let to = day_number(timestamp(&args[1])?, &tz)?;
+    Ok(Value::Int(to - from))
+}
+
+/// `date::business_days(from, to, [tz])`, Monday to Friday days from `from` up to, not
+/// including, `to`
+pub fn business_days(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 2, 3)?;
+    let tz = time_zone(args.get(2))?;
+    let from = day_number(timestamp(&args[0])?, &tz)?;
+    let to = day_number(timestamp(&args[1])?, &tz)?;
+    Ok(Value::Int(weekdays_before(to) - weekdays_before(from)))
+}
+
+/// `date::add_business_days(date, days, [tz])`, skipping Saturdays and Sundays
+pub fn add_business_days(value: &Value) -> EvalexprResult<Value> {
+    let args = arguments_between(value, 2, 3)?;
+    let tz = time_zone(args.get(2))?;
+    let date = local(timestamp(&args[0])?, &tz)?;
+    let days = timestamp(&args[1])?;
+    if days.abs() > 1_000_000 {
+        return error(format!("Can not add {} business days", days));
+    }
+
+    let start = day_number(date.to_unix(), &tz)?;
+    let mut day = start;
+    for _ in 0..days.abs() {
+        day += days.signum();
+        while !is_weekday(day) {
+            day += days.signum();
+        }
+    }
+    match date.add_days((day - start) as i32) {
+        Some(d) => Ok(Value::Int(d.to_unix())),
+        None => error(format!("Can not add {} business days", days)),
+    }
+}
+
+/// `date::span(seconds)`, the number of seconds as a duration. `30 minutes` is rewritten into
+/// `date::span(30 * 60)`, results of it are shown like `date::duration` shows them.
+pub fn span(value: &Value) -> EvalexprResult<Value> {
+    value.as_number()?;
+    Ok(value.clone())
+}
+
+/// `date::duration(seconds)`, e.g. `1d 2h 30m`
+pub fn duration(value: &Value) -> EvalexprResult<Value> {
+    display_duration(value).map(Value::String)
+}
+
+/// How results known to be durations are shown
+pub fn display_duration(value: &Value) -> EvalexprResult<String> {
+    let seconds = timestamp(value)?;
+    let mut left = seconds.abs();
+    let mut parts = Vec::new();
+    for &(unit, size) in &[("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if left >= size || (unit == "s" && parts.is_empty()) {
+            parts.push(format!("{}{}", left / size, unit));
+            left %= size;
+        }
+    }
+    let sign = if seconds < 0 { "-" } else { "" };
+    Ok(format!("{}{}", sign, parts.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONDAY: i64 = 19_723; // 2024-01-01
+
+    #[test]
+    fn civil_days() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        assert_eq!(days_from_civil(2024, 1, 1), MONDAY);
+        assert_eq!(days_from_civil(1, 1, 1), -719_162);
+    }
+
+    #[test]
+    fn month_lengths() {
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2023, 2), 28);
+        assert_eq!(days_in_month(1900, 2), 28);
+        assert_eq!(days_in_month(2000, 2), 29);
+        assert_eq!(days_in_month(2023, 4), 30);
+        assert_eq!(days_in_month(2023, 12), 31);
+    }
+
+    #[test]
+    fn business_days_between() {
+        let between = |from: i64, to: i64| weekdays_before(to) - weekdays_before(from);
+        assert_eq!(between(MONDAY, MONDAY + 7), 5);
+        assert_eq!(between(MONDAY + 4, MONDAY + 7), 1);
+        assert_eq!(between(MONDAY + 5, MONDAY + 6), 0);
+        assert_eq!(between(MONDAY + 7, MONDAY), -5);
+        assert_eq!(between(MONDAY, MONDAY + 366), 262);
+        // 1969-12-29 to 1970-01-05, either side of day 0
+        assert_eq!(between(-3, 4), 5);
+        assert!(is_weekday(0));
+        assert!(!is_weekday(2));
+        assert!(!is_weekday(MONDAY - 1));
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(&Value::Float(2.6)), Ok(3));
+        assert_eq!(timestamp(&Value::Int(-5)), Ok(-5));
+        assert!(timestamp(&Value::Float(f64::NAN)).is_err());
+        assert!(timestamp(&Value::Float(1e300)).is_err());
+    }
+
+    #[test]
+    fn durations() {
+        let shown = |seconds| display_duration(&Value::Int(seconds)).unwrap();
+        assert_eq!(shown(93_784), "1d 2h 3m 4s");
+        assert_eq!(shown(86_400), "1d");
+        assert_eq!(shown(-3_600), "-1h");
+        assert_eq!(shown(0), "0s");
+        assert_eq!(span(&Value::Int(60)), Ok(Value::Int(60)));
+    }
+}
@@ -0,0 +1,308 @@
+use {
+    crate::{
+        angle::{self, AngleUnit},
+        builtins, currency, dates,
+        format::ResultFormat,
+        header::Header,
+        kind::{Kind, Kinds},
+        preprocess::{self, Token},
+        programmer::{self, Word},
+    },
+    evalexpr::{
+        ContextWithMutableFunctions, ContextWithMutableVariables, EvalexprResult, Function,
+        HashMapContext, Value,
+    },
+    gio::SettingsExt,
+    std::cell::Cell,
+};
+
+/// Lines at the start of a document beginning with this are directives, not expressions
+pub const DIRECTIVE_PREFIX: &str = "#!";
+
+thread_local! {
+    /// Options of the latest context, for builtins evaluating expressions of their own
+    static OPTIONS: Cell<EvalOptions> = Cell::new(EvalOptions::default());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalOptions {
+    pub format: ResultFormat,
+    /// Treat integer literals as floats, so `7 / 2` is `3.5` rather than `3`
+    pub float_arithmetic: bool,
+    pub angle: AngleUnit,
+    /// Show integer results in decimal, hexadecimal and binary, wrapped to `word`
+    pub programmer: bool,
+    pub word: Word,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            format: ResultFormat::default(),
+            float_arithmetic: false,
+            angle: AngleUnit::Rad,
+            programmer: false,
+            word: Word::default(),
+        }
+    }
+}
+
+impl EvalOptions {
+    pub fn from_settings(settings: &gio::Settings) -> Self {
+        let default = Self::default();
+        Self {
+            format: ResultFormat::from_settings(settings),
+            float_arithmetic: settings
+                .get_string("precision-mode")
+                .map(|m| m == "float")
+                .unwrap_or(default.float_arithmetic),
+            angle: settings
+                .get_string("angle-unit")
+                .and_then(|a| AngleUnit::from_name(&a))
+                .unwrap_or(default.angle),
+            programmer: settings.get_boolean("programmer-mode"),
+            word: settings
+                .get_string("word-size")
+                .and_then(|w| Word::from_name(&w))
+                .unwrap_or(default.word),
+        }
+    }
+
+    /// Apply per-document overrides from the directive lines of `source`
+    pub fn with_directives(mut self, source: &str) -> Self {
+        self.format = self.format.with_directives(source);
+        for (key, value) in directives(source) {
+            match (key, value) {
+                ("precision-mode", "integer") => self.float_arithmetic = false,
+                ("precision-mode", "float") => self.float_arithmetic = true,
+                ("angle", a) => {
+                    if let Some(a) = AngleUnit::from_name(a) {
+                        self.angle = a;
+                    }
+                }
+                ("programmer", "true") => self.programmer = true,
+                ("programmer", "false") => self.programmer = false,
+                ("word-size", w) => {
+                    if let Some(w) = Word::from_name(w) {
+                        self.word = w;
+                        self.programmer = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+}
+
+pub fn context(options: &EvalOptions) -> HashMapContext {
+    OPTIONS.with(|o| o.set(*options));
+    let mut context = evalexpr::math_consts_context!().unwrap();
+    for (name, function) in builtins::native() {
+        context
+            .set_function(name.into(), Function::new(Box::new(function)))
+            .unwrap();
+    }
+    angle::register(&mut context, options.angle);
+    programmer::register(
+        &mut context,
+        if options.programmer {
+            Some(options.word)
+        } else {
+            None
+        },
+    );
+    context
+}
+
+/// The options of the evaluation in progress
+pub fn current_options() -> EvalOptions {
+    OPTIONS.with(Cell::get)
+}
+
+/// A fresh context with the options of the evaluation in progress
+pub fn current_context() -> HashMapContext {
+    context(&current_options())
+}
+
+/// Set the variables pinned in the header of `source`
+fn pin_variables(
+    source: &str,
+    options: &EvalOptions,
+    context: &mut HashMapContext,
+    kinds: &mut Kinds,
+) -> EvalexprResult<()> {
+    for (name, expr) in Header::parse(source).pinned {
+        let (value, kind) = eval_prepared(&prepare(&expr, options), context, kinds)?;
+        kinds.set(&name, kind);
+        context.set_value(name, value)?;
+    }
+    Ok(())
+}
+
+/// Evaluate prepared source, along with the kind of its value
+fn eval_prepared(
+    source: &str,
+    context: &mut HashMapContext,
+    kinds: &mut Kinds,
+) -> EvalexprResult<(Value, Kind)> {
+    let kind = kinds.infer(source)?;
+    Ok((evalexpr::eval_with_context_mut(source, context)?, kind))
+}
+
+/// Returns the `key: value` pairs of the directive lines at the start of `source`
+pub fn directives(source: &str) -> impl Iterator<Item = (&str, &str)> {
+    source
+        .lines()
+        .take_while(|l| l.trim_start().starts_with(DIRECTIVE_PREFIX))
+        .filter_map(|l| {
+            let mut kv = l.trim_start()[DIRECTIVE_PREFIX.len()..].splitn(2, ':');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => Some((k.trim(), v.trim())),
+                _ => None,
+            }
+        })
+}
+
+/// Blank out directive lines, keeping line numbers intact
+fn strip_directives(source: &str) -> String {
+    let mut in_header = true;
+    source
+        .lines()
+        .map(|l| {
+            in_header = in_header && l.trim_start().starts_with(DIRECTIVE_PREFIX);
+            if in_header {
+                ""
+            } else {
+                l
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Rewrite `source` into plain evalexpr syntax
+pub fn prepare(source: &str, options: &EvalOptions) -> String {
+    let source = preprocess::symbolic_calls(&strip_directives(source));
+    let source = preprocess::comprehensions(&source);
+    let source = preprocess::closure_calls(&source, &|body| prepare(body, options));
+    let source = preprocess::int_literals(&source);
+    let source =
+        preprocess::currency_units(&preprocess::duration_units(&source), &currency::is_known);
+    let source = preprocess::angle_units(&source);
+    let source = preprocess::percentages(&source);
+    let source = preprocess::ranges(&source);
+    let source = preprocess::bitwise_operators(&source, options.programmer);
+    if options.float_arithmetic {
+        preprocess::float_literals(&source)
+    } else {
+        source
+    }
+}
+
+/// Evaluate `source`, returning its value, what kind of value it is and the context holding
+/// the variables it defines
+fn eval_with_kind(
+    source: &str,
+    options: EvalOptions,
+) -> EvalexprResult<(Value, Kind, HashMapContext)> {
+    let options = options.with_directives(source);
+    let mut context = context(&options);
+    let mut kinds = Kinds::new();
+    pin_variables(source, &options, &mut context, &mut kinds)?;
+    let (value, kind) = eval_prepared(&prepare(source, &options), &mut context, &mut kinds)?;
+    Ok((value, kind, context))
+}
+
+/// Evaluate `source`, returning its value and the context holding the variables it defines
+pub fn eval_with_context(
+    source: &str,
+    options: EvalOptions,
+) -> EvalexprResult<(Value, HashMapContext)> {
+    eval_with_kind(source, options).map(|(value, _, context)| (value, context))
+}
+
+/// One `;`-separated statement of a document and what it evaluated to, along with what kind of
+/// value that is
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub source: String,
+    pub result: EvalexprResult<(Value, Kind)>,
+}
+
+/// Evaluate `source` a statement at a time, each one sees the variables set by those before it
+pub fn eval_statements(source: &str, options: EvalOptions) -> Vec<Statement> {
+    let options = options.with_directives(source);
+    let mut context = context(&options);
+    let mut kinds = Kinds::new();
+    if let Err(e) = pin_variables(source, &options, &mut context, &mut kinds) {
+        return vec![Statement {
+            source: String::from("Pinned variables"),
+            result: Err(e),
+        }];
+    }
+    statements(&strip_directives(source))
+        .into_iter()
+        .map(|statement| Statement {
+            source: statement.trim().to_string(),
+            result: eval_prepared(&prepare(statement, &options), &mut context, &mut kinds),
+        })
+        .collect()
+}
+
+/// Split `source` at the `;` ending each statement, leaving out empty ones
+fn statements(source: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let (mut start, mut pos, mut depth) = (0, 0, 0);
+    for token in preprocess::tokenize(source) {
+        match token {
+            Token::Punct("(") => depth += 1,
+            Token::Punct(")") => depth -= 1,
+            Token::Punct(";") if depth == 0 => {
+                statements.push(&source[start..pos]);
+                start = pos + 1;
+            }
+            _ => {}
+        }
+        pos += token.text().len();
+    }
+    statements.push(&source[start..]);
+    statements.retain(|s| !s.trim().is_empty());
+    statements
+}
+
+pub fn eval(source: &str, options: EvalOptions) -> EvalexprResult<Value> {
+    eval_with_context(source, options).map(|(value, _)| value)
+}
+
+/// Show `value` the way results are shown, dates and durations as such rather than seconds and
+/// amounts of money with their currency
+pub fn format_value(value: &Value, kind: &Kind, options: &EvalOptions) -> String {
+    let shown = match kind {
+        Kind::Date => dates::display_date(value).ok(),
+        Kind::Duration => dates::display_duration(value).ok(),
+        Kind::Money(code) if !code.is_empty() && value.as_number().is_ok() => {
+            Some(format!("{} {}", options.format.format(value), code))
+        }
+        _ => None,
+    };
+    if let Some(shown) = shown {
+        return shown;
+    }
+    match *value {
+        Value::Int(i) if options.programmer => programmer::format(i, options.word),
+        Value::Float(f) if options.programmer && f.fract() == 0.0 && f.abs() < 2f64.powi(63) => {
+            programmer::format(f as i64, options.word)
+        }
+        ref value => options.format.format(value),
+    }
+}
+
+pub fn eval_to_string(source: &str, options: EvalOptions) -> EvalexprResult<String> {
+    let (value, kind, _) = eval_with_kind(source, options)?;
+    Ok(format_value(
+        &value,
+        &kind,
+        &options.with_directives(source),
+    ))
+}
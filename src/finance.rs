@@ -0,0 +1,310 @@
+use evalexpr::{EvalexprError, EvalexprResult, Value};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Numeric arguments, between `min` and `max` of them, missing optional ones are 0
+fn args(value: &Value, min: usize, max: usize) -> EvalexprResult<Vec<f64>> {
+    let mut args = match value {
+        Value::Tuple(t) => t
+            .iter()
+            .map(Value::as_number)
+            .collect::<EvalexprResult<_>>()?,
+        v => vec![v.as_number()?],
+    };
+    if args.len() < min || args.len() > max {
+        return Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: min,
+            actual: args.len(),
+        });
+    }
+    args.resize(max, 0.0);
+    Ok(args)
+}
+
+/// Cash flows given either as the arguments or as tuples
+fn flatten(values: &[Value]) -> EvalexprResult<Vec<f64>> {
+    let mut out = Vec::new();
+    for v in values {
+        match v {
+            Value::Tuple(t) => out.extend(flatten(t)?),
+            v => out.push(v.as_number()?),
+        }
+    }
+    Ok(out)
+}
+
+/// Find a root of `f` near `guess` with Newton's method
+fn newton(guess: f64, f: impl Fn(f64) -> f64) -> EvalexprResult<f64> {
+    let mut x = guess;
+    for _ in 0..100 {
+        let y = f(x);
+        let h = 1e-7 * x.abs().max(1e-3);
+        let slope = (f(x + h) - y) / h;
+        let next = x - y / slope;
+        if !next.is_finite() {
+            break;
+        }
+        if (next - x).abs() < 1e-12 {
+            return Ok(next);
+        }
+        x = next;
+    }
+    Err(EvalexprError::CustomMessage(String::from(
+        "Could not find a rate, try another guess",
+    )))
+}
+
+/// Value left at the end when starting at `pv` and paying `pmt` every period, 0 for a
+/// fully paid loan
+fn balance(rate: f64, nper: f64, pmt: f64, pv: f64, kind: f64) -> f64 {
+    if rate == 0.0 {
+        return pv + pmt * nper;
+    }
+    let growth = (1.0 + rate).powf(nper);
+    pv * growth + pmt * (1.0 + rate * kind) * (growth - 1.0) / rate
+}
+
+/// Payment per period of a loan or investment: `fin::pmt(rate, nper, pv, [fv], [type])`.
+/// Like in spreadsheets money paid out is negative, and `type` is 1 for payments at the start
+/// of each period instead of the end.
+pub fn pmt(value: &Value) -> EvalexprResult<Value> {
+    let a = args(value, 3, 5)?;
+    let (rate, nper, pv, fv, kind) = (a[0], a[1], a[2], a[3], a[4]);
+    Ok(Value::Float(if rate == 0.0 {
+        -(pv + fv) / nper
+    } else {
+        let growth = (1.0 + rate).powf(nper);
+        -rate * (pv * growth + fv) / ((1.0 + rate * kind) * (growth - 1.0))
+    }))
+}
+
+/// Present value: `fin::pv(rate, nper, pmt, [fv], [type])`
+pub fn pv(value: &Value) -> EvalexprResult<Value> {
+    let a = args(value, 3, 5)?;
+    let (rate, nper, pmt, fv, kind) = (a[0], a[1], a[2], a[3], a[4]);
+    Ok(Value::Float(if rate == 0.0 {
+        -(fv + pmt * nper)
+    } else {
+        -(fv + balance(rate, nper, pmt, 0.0, kind)) / (1.0 + rate).powf(nper)
+    }))
+}
+
+/// Future value: `fin::fv(rate, nper, pmt, [pv], [type])`
+pub fn fv(value: &Value) -> EvalexprResult<Value> {
+    let a = args(value, 3, 5)?;
+    Ok(Value::Float(-balance(a[0], a[1], a[2], a[3], a[4])))
+}
+
+/// Number of periods: `fin::nper(rate, pmt, pv, [fv], [type])`
+pub fn nper(value: &Value) -> EvalexprResult<Value> {
+    let a = args(value, 3, 5)?;
+    let (rate, pmt, pv, fv, kind) = (a[0], a[1], a[2], a[3], a[4]);
+    Ok(Value::Float(if rate == 0.0 {
+        -(pv + fv) / pmt
+    } else {
+        let p = pmt * (1.0 + rate * kind);
+        ((p - fv * rate) / (p + pv * rate)).ln() / (1.0 + rate).ln()
+    }))
+}
+
+/// Interest rate per period: `fin::rate(nper, pmt, pv, [fv], [type], [guess])`
+pub fn rate(value: &Value) -> EvalexprResult<Value> {
+    let a = args(value, 3, 6)?;
+    let (nper, pmt, pv, fv, kind) = (a[0], a[1], a[2], a[3], a[4]);
+    let guess = if a[5] == 0.0 { 0.1 } else { a[5] };
+    newton(guess, |r| balance(r, nper, pmt, pv, kind) + fv).map(Value::Float)
+}
+
+fn npv_of(rate: f64, flows: &[f64], first_period: i32) -> f64 {
+    flows
+        .iter()
+        .zip(first_period..)
+        .map(|(flow, period)| flow / (1.0 + rate).powi(period))
+        .sum()
+}
+
+/// Net present value of cash flows at the end of periods 1, 2, …: `fin::npv(rate, flows…)`
+pub fn npv(value: &Value) -> EvalexprResult<Value> {
+    match value.as_tuple()?.split_first() {
+        Some((rate, flows)) if !flows.is_empty() => {
+            Ok(Value::Float(npv_of(rate.as_number()?, &flatten(flows)?, 1)))
+        }
+        _ => Err(EvalexprError::CustomMessage(String::from(
+            "Expected a rate and at least one cash flow",
+        ))),
+    }
+}
+
+/// Internal rate of return of cash flows, the first at period 0: `fin::irr(flows, [guess])`
+pub fn irr(value: &Value) -> EvalexprResult<Value> {
+    let (flows, guess) = match value.as_tuple()?.as_slice() {
+        [Value::Tuple(flows), guess] => (flatten(flows)?, guess.as_number()?),
+        flows => (flatten(flows)?, 0.1),
+    };
+    if !(flows.iter().any(|f| *f > 0.0) && flows.iter().any(|f| *f < 0.0)) {
+        return Err(EvalexprError::CustomMessage(String::from(
+            "Expected at least one positive and one negative cash flow",
+        )));
+    }
+    newton(guess, |r| npv_of(r, &flows, 0)).map(Value::Float)
+}
+
+/// Net present value of cash flows on irregular dates, with a year of 365 days:
+/// `fin::xnpv(rate, flows, dates)`. Dates are seconds since 1970, as `date::parse` returns
+/// them, and only whole days between them count, so a daylight saving change does not matter.
+pub fn xnpv(value: &Value) -> EvalexprResult<Value> {
+    match value.as_tuple()?.as_slice() {
+        [rate, Value::Tuple(flows), Value::Tuple(dates)] => {
+            let (rate, flows, dates) = (rate.as_number()?, flatten(flows)?, flatten(dates)?);
+            if flows.len() != dates.len() {
+                return Err(EvalexprError::CustomMessage(format!(
+                    "Expected as many dates as cash flows, got {} and {}",
+                    dates.len(),
+                    flows.len()
+                )));
+            }
+            let days = |date: f64| ((date - dates[0]) / SECONDS_PER_DAY).round();
+            Ok(Value::Float(
+                flows
+                    .iter()
+                    .zip(&dates)
+                    .map(|(flow, date)| flow / (1.0 + rate).powf(days(*date) / 365.0))
+                    .sum(),
+            ))
+        }
+        _ => Err(EvalexprError::CustomMessage(String::from(
+            "Expected a rate, a tuple of cash flows and a tuple of dates",
+        ))),
+    }
+}
+
+/// Repayment schedule of a loan: `fin::amortize(rate, nper, pv)` returns a tuple of
+/// `(period, payment, interest, principal, balance)` rows
+pub fn amortize(value: &Value) -> EvalexprResult<Value> {
+    let a = args(value, 3, 3)?;
+    let (rate, nper, mut balance) = (a[0], a[1], a[2]);
+    if nper < 1.0 || nper.fract() != 0.0 || nper > 10_000.0 {
+        return Err(EvalexprError::CustomMessage(format!(
+            "Expected a whole number of periods up to 10000, got {}",
+            nper
+        )));
+    }
+    let payment = pmt(value)?.as_number()?;
+
+    let rows = (1..=nper as i64)
+        .map(|period| {
+            let interest = -balance * rate;
+            let principal = payment - interest;
+            balance += principal;
+            Value::Tuple(vec![
+                Value::Int(period),
+                Value::Float(payment),
+                Value::Float(interest),
+                Value::Float(principal),
+                Value::Float(balance),
+            ])
+        })
+        .collect();
+    Ok(Value::Tuple(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(f: fn(&Value) -> EvalexprResult<Value>, args: &[f64]) -> f64 {
+        f(&Value::Tuple(
+            args.iter().map(|a| Value::Float(*a)).collect(),
+        ))
+        .unwrap()
+        .as_number()
+        .unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    // Expected values are what spreadsheets give for the same arguments
+
+    #[test]
+    fn time_value_of_money() {
+        assert_close(
+            call(pmt, &[0.05 / 12.0, 360.0, 200_000.0]),
+            -1073.643246,
+            1e-6,
+        );
+        assert_close(call(pmt, &[0.0, 10.0, 1000.0]), -100.0, 1e-9);
+        assert_close(call(pv, &[0.08 / 12.0, 240.0, 500.0]), -59_777.145851, 1e-6);
+        assert_close(
+            call(fv, &[0.06 / 12.0, 10.0, -200.0, -500.0, 1.0]),
+            2581.403374,
+            1e-6,
+        );
+        assert_close(
+            call(nper, &[0.12 / 12.0, -100.0, -1000.0, 10_000.0, 1.0]),
+            59.673866,
+            1e-6,
+        );
+        assert_close(call(rate, &[48.0, -200.0, 8000.0]), 0.007701472, 1e-9);
+    }
+
+    #[test]
+    fn cash_flows() {
+        assert_close(
+            call(npv, &[0.1, -10_000.0, 3000.0, 4200.0, 6800.0]),
+            1188.443412,
+            1e-6,
+        );
+        assert_close(
+            call(
+                irr,
+                &[-70_000.0, 12_000.0, 15_000.0, 18_000.0, 21_000.0, 26_000.0],
+            ),
+            0.086630948,
+            1e-9,
+        );
+        assert!(irr(&Value::Tuple(vec![Value::Float(1.0), Value::Float(2.0)])).is_err());
+
+        let tuple =
+            |values: &[f64]| Value::Tuple(values.iter().map(|v| Value::Float(*v)).collect());
+        // Dates of the spreadsheet's day numbers, which count from 1899-12-30
+        let xnpv = |days: &[f64]| {
+            let dates = days.iter().map(|d| (d - 25_569.0) * SECONDS_PER_DAY);
+            xnpv(&Value::Tuple(vec![
+                Value::Float(0.09),
+                tuple(&[-10_000.0, 2750.0, 4250.0, 3250.0, 2750.0]),
+                tuple(&dates.collect::<Vec<_>>()),
+            ]))
+            .unwrap()
+            .as_number()
+            .unwrap()
+        };
+        let days = [39448.0, 39508.0, 39751.0, 39859.0, 39904.0];
+        assert_close(xnpv(&days), 2086.647602, 1e-6);
+        // Dates an hour early, across a daylight saving change, are still whole days apart
+        let early = [39448.0, 39508.0 - 1.0 / 24.0, 39751.0, 39859.0, 39904.0];
+        assert_close(xnpv(&early), 2086.647602, 1e-6);
+    }
+
+    #[test]
+    fn amortization() {
+        let rows = amortize(&Value::Tuple(vec![
+            Value::Float(0.01),
+            Value::Int(12),
+            Value::Float(10_000.0),
+        ]))
+        .unwrap()
+        .as_tuple()
+        .unwrap();
+        assert_eq!(rows.len(), 12);
+        let last = rows[11].as_tuple().unwrap();
+        assert_close(last[1].as_number().unwrap(), -888.487887, 1e-6);
+        assert_close(last[4].as_number().unwrap(), 0.0, 1e-6);
+    }
+}
@@ -0,0 +1,328 @@
+use {evalexpr::Value, gio::SettingsExt, std::ops::Range};
+
+/// Powers of ten significant notation writes out in full, beyond them it switches to scientific
+const SIGNIFICANT_EXPONENTS: Range<i32> = -4..15;
+/// Largest number of decimals or significant digits, the bound of the `result-precision` key
+const MAX_PRECISION: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notation {
+    Auto,
+    Fixed,
+    Significant,
+    Scientific,
+    Engineering,
+}
+
+impl Notation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::Auto),
+            "fixed" => Some(Self::Fixed),
+            "significant" => Some(Self::Significant),
+            "scientific" => Some(Self::Scientific),
+            "engineering" => Some(Self::Engineering),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Base {
+    Dec,
+    Hex,
+    Oct,
+    Bin,
+}
+
+impl Base {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dec" => Some(Self::Dec),
+            "hex" => Some(Self::Hex),
+            "oct" => Some(Self::Oct),
+            "bin" => Some(Self::Bin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResultFormat {
+    pub notation: Notation,
+    pub precision: usize,
+    pub separators: bool,
+    pub base: Base,
+}
+
+impl Default for ResultFormat {
+    fn default() -> Self {
+        Self {
+            notation: Notation::Auto,
+            precision: 6,
+            separators: false,
+            base: Base::Dec,
+        }
+    }
+}
+
+impl ResultFormat {
+    pub fn from_settings(settings: &gio::Settings) -> Self {
+        let default = Self::default();
+        Self {
+            notation: settings
+                .get_string("result-notation")
+                .and_then(|n| Notation::from_name(&n))
+                .unwrap_or(default.notation),
+            precision: (settings.get_int("result-precision").max(0) as usize).min(MAX_PRECISION),
+            separators: settings.get_boolean("result-separators"),
+            base: settings
+                .get_string("result-base")
+                .and_then(|b| Base::from_name(&b))
+                .unwrap_or(default.base),
+        }
+    }
+
+    /// Apply per-document overrides, `#! key: value` lines at the start of the document
+    pub fn with_directives(mut self, source: &str) -> Self {
+        for (key, value) in crate::eval::directives(source) {
+            match key {
+                "notation" => {
+                    if let Some(n) = Notation::from_name(value) {
+                        self.notation = n;
+                    }
+                }
+                "precision" => {
+                    if let Ok(p @ 0..=MAX_PRECISION) = value.parse() {
+                        self.precision = p;
+                    }
+                }
+                "separators" => {
+                    if let Ok(s) = value.parse() {
+                        self.separators = s;
+                    }
+                }
+                "base" => {
+                    if let Some(b) = Base::from_name(value) {
+                        self.base = b;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+
+    pub fn format(&self, value: &Value) -> String {
+        match value {
+            Value::Int(i) => self.format_int(*i),
+            Value::Float(f) => self.format_float(*f),
+            Value::Tuple(t) => format!(
+                "({})",
+                t.iter()
+                    .map(|v| self.format(v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::String(s) => s.clone(),
+            Value::Empty => String::new(),
+            v => v.to_string(),
+        }
+    }
+
+    fn format_int(&self, i: i64) -> String {
+        let (prefix, digits, group) = match self.base {
+            Base::Dec => match self.notation {
+                Notation::Auto => ("", i.unsigned_abs().to_string(), 3),
+                _ => return self.format_float(i as f64),
+            },
+            Base::Hex => ("0x", format!("{:X}", i.unsigned_abs()), 4),
+            Base::Oct => ("0o", format!("{:o}", i.unsigned_abs()), 3),
+            Base::Bin => ("0b", format!("{:b}", i.unsigned_abs()), 4),
+        };
+        let digits = match (self.separators, self.base) {
+            (false, _) => digits,
+            (true, Base::Dec) => group_digits(&digits, ',', group),
+            (true, _) => group_digits(&digits, '_', group),
+        };
+        format!("{}{}{}", if i < 0 { "-" } else { "" }, prefix, digits)
+    }
+
+    fn format_float(&self, f: f64) -> String {
+        if !f.is_finite() {
+            return f.to_string();
+        }
+
+        if self.base != Base::Dec
+            && f.fract() == 0.0
+            && f >= i64::MIN as f64
+            && f <= i64::MAX as f64
+        {
+            return self.format_int(f as i64);
+        }
+
+        let out = match self.notation {
+            Notation::Auto => f.to_string(),
+            Notation::Fixed => format!("{:.*}", self.precision, f),
+            Notation::Significant => format_significant(f, self.precision.max(1)),
+            Notation::Scientific => format!("{:.*e}", self.precision, f),
+            Notation::Engineering => format_engineering(f, self.precision),
+        };
+
+        match (self.separators, self.notation) {
+            (true, Notation::Auto) | (true, Notation::Fixed) | (true, Notation::Significant) => {
+                separate_integer_part(&out)
+            }
+            _ => out,
+        }
+    }
+}
+
+fn format_significant(f: f64, digits: usize) -> String {
+    if f == 0.0 {
+        return format!("{:.*}", digits - 1, 0.0);
+    }
+    // The exponent after rounding, 9.99 to two digits is 10
+    let scientific = format!("{:.*e}", digits - 1, f);
+    let exp = scientific
+        .rsplit('e')
+        .next()
+        .and_then(|e| e.parse::<i32>().ok())
+        .unwrap_or_default();
+    if !SIGNIFICANT_EXPONENTS.contains(&exp) {
+        return scientific;
+    }
+    let decimals = digits as i32 - 1 - exp;
+    if decimals >= 0 {
+        format!("{:.*}", decimals as usize, f)
+    } else {
+        let scale = 10f64.powi(-decimals);
+        format!("{:.0}", (f / scale).round() * scale)
+    }
+}
+
+fn format_engineering(f: f64, precision: usize) -> String {
+    if f == 0.0 {
+        return format!("{:.*}e0", precision, 0.0);
+    }
+    let exp = f.abs().log10().floor() as i32;
+    let mut eng = exp - exp.rem_euclid(3);
+    let mut mantissa = f / 10f64.powi(eng);
+    // Rounding may push the mantissa up to the next power of a thousand
+    if format!("{:.*}", precision, mantissa.abs())
+        .parse::<f64>()
+        .unwrap_or(0.0)
+        >= 1000.0
+    {
+        mantissa /= 1000.0;
+        eng += 3;
+    }
+    format!("{:.*}e{}", precision, mantissa, eng)
+}
+
+fn separate_integer_part(s: &str) -> String {
+    let (sign, rest) = match s.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", s),
+    };
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or_else(|| rest.len());
+    format!(
+        "{}{}{}",
+        sign,
+        group_digits(&rest[..end], ',', 3),
+        &rest[end..]
+    )
+}
+
+fn group_digits(digits: &str, sep: char, size: usize) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / size);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % size == 0 {
+            out.push(sep);
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(notation: Notation, precision: usize, value: Value) -> String {
+        ResultFormat {
+            notation,
+            precision,
+            ..ResultFormat::default()
+        }
+        .format(&value)
+    }
+
+    #[test]
+    fn notations() {
+        let f = Value::Float(1234.5678);
+        assert_eq!(format(Notation::Auto, 3, f.clone()), "1234.5678");
+        assert_eq!(format(Notation::Fixed, 2, f.clone()), "1234.57");
+        assert_eq!(format(Notation::Significant, 3, f.clone()), "1230");
+        assert_eq!(format(Notation::Scientific, 2, f.clone()), "1.23e3");
+        assert_eq!(
+            format(Notation::Engineering, 2, Value::Float(12345.0)),
+            "12.35e3"
+        );
+        assert_eq!(
+            format(Notation::Engineering, 1, Value::Float(999.96)),
+            "1.0e3"
+        );
+    }
+
+    #[test]
+    fn integers_follow_the_notation() {
+        assert_eq!(format(Notation::Auto, 2, Value::Int(12345)), "12345");
+        assert_eq!(format(Notation::Fixed, 2, Value::Int(12345)), "12345.00");
+        assert_eq!(format(Notation::Significant, 2, Value::Int(12345)), "12000");
+        assert_eq!(
+            format(Notation::Scientific, 1, Value::Int(-12345)),
+            "-1.2e4"
+        );
+    }
+
+    #[test]
+    fn significant_digits() {
+        assert_eq!(format_significant(9.99, 2), "10");
+        assert_eq!(format_significant(0.0999, 2), "0.10");
+        assert_eq!(format_significant(0.012345, 3), "0.0123");
+        assert_eq!(format_significant(-2.7, 1), "-3");
+        assert_eq!(format_significant(0.0, 3), "0.00");
+        assert_eq!(format_significant(1e300, 3), "1.00e300");
+        assert_eq!(format_significant(1.5e-7, 2), "1.5e-7");
+    }
+
+    #[test]
+    fn separators_and_bases() {
+        let format = ResultFormat {
+            separators: true,
+            ..ResultFormat::default()
+        };
+        assert_eq!(format.format(&Value::Int(-1234567)), "-1,234,567");
+        assert_eq!(format.format(&Value::Float(1234.5)), "1,234.5");
+        let hex = ResultFormat {
+            base: Base::Hex,
+            ..format
+        };
+        assert_eq!(hex.format(&Value::Int(0xABCDEF)), "0xAB_CDEF");
+        assert_eq!(hex.format(&Value::Float(255.0)), "0xFF");
+    }
+
+    #[test]
+    fn precision_directive() {
+        let precision = |source| ResultFormat::default().with_directives(source).precision;
+        assert_eq!(precision("#! precision: 2"), 2);
+        assert_eq!(precision("#! precision: 15"), 15);
+        // Out of the range of the preference, as are huge precisions that could not be formatted
+        assert_eq!(precision("#! precision: 16"), 6);
+        assert_eq!(precision("#! precision: 4000000000"), 6);
+        assert_eq!(precision("#! precision: -1"), 6);
+    }
+}
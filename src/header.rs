@@ -0,0 +1,123 @@
+use crate::eval;
+
+/// Newest version of the header this build understands
+pub const VERSION: u32 = 1;
+
+/// Optional block of directive lines at the start of a .mee file, for example
+///
+/// ```text
+/// #! version: 1
+/// #! title: Loan
+/// #! author: Jane Doe
+/// #! precision-mode: float
+/// #! notation: fixed
+/// #! pin: rate = 0.05 / 12
+/// ```
+///
+/// Evaluation settings are applied by `EvalOptions::with_directives`. Files without
+/// a header are evaluated with the preferences.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Header {
+    /// `None` for files written before the header was versioned
+    pub version: Option<u32>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// `(name, expression)` of variables set before the document is evaluated
+    pub pinned: Vec<(String, String)>,
+}
+
+impl Header {
+    pub fn parse(source: &str) -> Self {
+        let mut header = Self::default();
+        for (key, value) in eval::directives(source) {
+            match key {
+                "version" => header.version = value.parse().ok(),
+                "title" if !value.is_empty() => header.title = Some(value.to_string()),
+                "author" if !value.is_empty() => header.author = Some(value.to_string()),
+                "pin" => {
+                    let mut kv = value.splitn(2, '=');
+                    if let (Some(name), Some(expr)) = (kv.next(), kv.next()) {
+                        header
+                            .pinned
+                            .push((name.trim().to_string(), expr.trim().to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        header
+    }
+
+    /// Whether the file was written for a newer version of the format than this one
+    pub fn is_newer(&self) -> bool {
+        self.version.map(|v| v > VERSION).unwrap_or(false)
+    }
+}
+
+/// `source` with an older `version` directive raised to the version this build saves. Files
+/// without one, like those from before the header was versioned, and files from a version this
+/// build does not know are kept as they are.
+pub fn update_version(source: &str) -> String {
+    let mut start = 0;
+    for l in source.split_inclusive('\n') {
+        let trimmed = l.trim_start();
+        if !trimmed.starts_with(eval::DIRECTIVE_PREFIX) {
+            break;
+        }
+        let mut kv = trimmed[eval::DIRECTIVE_PREFIX.len()..].splitn(2, ':');
+        if let (Some("version"), Some(version)) = (kv.next().map(str::trim), kv.next()) {
+            match version.trim().parse::<u32>() {
+                Ok(v) if v < VERSION => {
+                    let end = start + l.trim_end().len();
+                    return format!(
+                        "{}{} version: {}{}",
+                        &source[..start],
+                        eval::DIRECTIVE_PREFIX,
+                        VERSION,
+                        &source[end..]
+                    );
+                }
+                _ => break,
+            }
+        }
+        start += l.len();
+    }
+    source.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let header = Header::parse("#! version: 1\n#! title: Loan\n#! pin: r = 0.05 / 12\nr * 2");
+        assert_eq!(header.version, Some(1));
+        assert_eq!(header.title.as_deref(), Some("Loan"));
+        assert_eq!(header.pinned, vec![("r".into(), "0.05 / 12".into())]);
+        assert!(!header.is_newer());
+        assert!(Header::parse("#! version: 2").is_newer());
+    }
+
+    #[test]
+    fn older_versions_are_updated() {
+        assert_eq!(
+            update_version("#! title: Loan\n#! version: 0\r\n1"),
+            "#! title: Loan\n#! version: 1\r\n1"
+        );
+    }
+
+    #[test]
+    fn other_files_are_kept() {
+        assert_eq!(update_version("1 + 1"), "1 + 1");
+        assert_eq!(
+            update_version("#! title: Loan\n1 + 1"),
+            "#! title: Loan\n1 + 1"
+        );
+        assert_eq!(update_version("#! version: 1\n1"), "#! version: 1\n1");
+        assert_eq!(update_version("#! version: 2\n1"), "#! version: 2\n1");
+        assert_eq!(update_version("#! version: next\n1"), "#! version: next\n1");
+        // Only the header counts
+        assert_eq!(update_version("1\n#! version: 0"), "1\n#! version: 0");
+    }
+}
@@ -0,0 +1,254 @@
+use {
+    glib::clone,
+    gtk::{prelude::*, TextBuffer},
+    std::{cell::RefCell, rc::Rc},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+struct Edit {
+    kind: Kind,
+    /// Character offset of the start of `text`
+    offset: i32,
+    text: String,
+    /// Made of single keystrokes, so later keystrokes may be merged in
+    typed: bool,
+}
+
+impl Edit {
+    fn new(kind: Kind, offset: i32, text: &str) -> Self {
+        Self {
+            kind,
+            offset,
+            text: text.to_string(),
+            typed: text.chars().count() == 1,
+        }
+    }
+
+    fn end(&self) -> i32 {
+        self.offset + self.text.chars().count() as i32
+    }
+
+    /// Merge `next` into this edit if it continues the same word, a new word starts after whitespace
+    fn merge(&mut self, next: &Edit) -> bool {
+        if !self.typed || !next.typed || self.kind != next.kind {
+            return false;
+        }
+        let c = next.text.chars().next().unwrap();
+        let starts_word =
+            |before: char, after: char| before.is_whitespace() && !after.is_whitespace();
+
+        match self.kind {
+            // Typing, or deleting forwards with the Delete key
+            Kind::Insert if next.offset == self.end() => {}
+            Kind::Delete if next.offset == self.offset => {}
+            // Deleting backwards with Backspace
+            Kind::Delete if next.end() == self.offset => {
+                if starts_word(c, self.text.chars().next().unwrap()) {
+                    return false;
+                }
+                self.offset = next.offset;
+                self.text.insert(0, c);
+                return true;
+            }
+            _ => return false,
+        }
+
+        if starts_word(self.text.chars().last().unwrap(), c) {
+            return false;
+        }
+        self.text.push(c);
+        true
+    }
+
+    fn apply(&self, buffer: &TextBuffer, forwards: bool) {
+        let insert = (self.kind == Kind::Insert) == forwards;
+        let mut start = buffer.get_iter_at_offset(self.offset);
+        if insert {
+            buffer.insert(&mut start, &self.text);
+        } else {
+            let mut end = buffer.get_iter_at_offset(self.end());
+            buffer.delete(&mut start, &mut end);
+        }
+        // Leave the cursor where the change happened
+        let cursor = if insert { self.end() } else { self.offset };
+        buffer.place_cursor(&buffer.get_iter_at_offset(cursor));
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stacks {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// Set while undoing or redoing, those changes are not recorded
+    applying: bool,
+}
+
+impl Stacks {
+    fn record(&mut self, edit: Edit) {
+        if self.applying {
+            return;
+        }
+        self.redo.clear();
+        if let Some(last) = self.undo.last_mut() {
+            if last.merge(&edit) {
+                return;
+            }
+        }
+        self.undo.push(edit);
+    }
+}
+
+/// Undo and redo for a text buffer, consecutive keystrokes are undone a word at a time
+#[derive(Debug)]
+pub struct History {
+    buffer: TextBuffer,
+    stacks: Rc<RefCell<Stacks>>,
+}
+
+impl History {
+    pub fn new(buffer: &TextBuffer) -> Self {
+        let stacks = Rc::new(RefCell::new(Stacks::default()));
+
+        buffer.connect_insert_text(clone!(@strong stacks => move |_, iter, text| {
+            stacks
+                .borrow_mut()
+                .record(Edit::new(Kind::Insert, iter.get_offset(), text));
+        }));
+        buffer.connect_delete_range(clone!(@strong stacks => move |buffer, start, end| {
+            let text = buffer
+                .get_text(start, end, true)
+                .map(|t| t.to_string())
+                .unwrap_or_default();
+            stacks
+                .borrow_mut()
+                .record(Edit::new(Kind::Delete, start.get_offset(), &text));
+        }));
+
+        Self {
+            buffer: buffer.clone(),
+            stacks,
+        }
+    }
+
+    pub fn undo(&self) {
+        let edit = self.stacks.borrow_mut().undo.pop();
+        if let Some(edit) = edit {
+            self.apply(&edit, false);
+            self.stacks.borrow_mut().redo.push(edit);
+        }
+    }
+
+    pub fn redo(&self) {
+        let edit = self.stacks.borrow_mut().redo.pop();
+        if let Some(edit) = edit {
+            self.apply(&edit, true);
+            self.stacks.borrow_mut().undo.push(edit);
+        }
+    }
+
+    /// Forget all edits, for when another document is loaded
+    pub fn clear(&self) {
+        let mut stacks = self.stacks.borrow_mut();
+        stacks.undo.clear();
+        stacks.redo.clear();
+    }
+
+    fn apply(&self, edit: &Edit, forwards: bool) {
+        self.stacks.borrow_mut().applying = true;
+        edit.apply(&self.buffer, forwards);
+        self.stacks.borrow_mut().applying = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(stacks: &mut Stacks, offset: i32, text: &str) -> i32 {
+        for (i, c) in text.chars().enumerate() {
+            stacks.record(Edit::new(Kind::Insert, offset + i as i32, &c.to_string()));
+        }
+        offset + text.chars().count() as i32
+    }
+
+    fn undone(stacks: &Stacks) -> Vec<(Kind, i32, &str)> {
+        stacks
+            .undo
+            .iter()
+            .map(|e| (e.kind, e.offset, e.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn keystrokes_merge_a_word_at_a_time() {
+        let mut stacks = Stacks::default();
+        let end = typed(&mut stacks, 0, "x = 12");
+        typed(&mut stacks, end, " + y");
+        assert_eq!(
+            undone(&stacks),
+            vec![
+                (Kind::Insert, 0, "x "),
+                (Kind::Insert, 2, "= "),
+                (Kind::Insert, 4, "12 "),
+                (Kind::Insert, 7, "+ "),
+                (Kind::Insert, 9, "y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn pastes_and_jumps_are_not_merged() {
+        let mut stacks = Stacks::default();
+        typed(&mut stacks, 0, "ab");
+        stacks.record(Edit::new(Kind::Insert, 2, "cd"));
+        typed(&mut stacks, 0, "z");
+        assert_eq!(
+            undone(&stacks),
+            vec![
+                (Kind::Insert, 0, "ab"),
+                (Kind::Insert, 2, "cd"),
+                (Kind::Insert, 0, "z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn deletions_merge_in_either_direction() {
+        let mut stacks = Stacks::default();
+        // Backspace over "a b", split where typing it would have been
+        stacks.record(Edit::new(Kind::Delete, 2, "b"));
+        stacks.record(Edit::new(Kind::Delete, 1, " "));
+        stacks.record(Edit::new(Kind::Delete, 0, "a"));
+        assert_eq!(
+            undone(&stacks),
+            vec![(Kind::Delete, 2, "b"), (Kind::Delete, 0, "a ")]
+        );
+
+        let mut stacks = Stacks::default();
+        // The Delete key at the same offset
+        stacks.record(Edit::new(Kind::Delete, 4, "d"));
+        stacks.record(Edit::new(Kind::Delete, 4, "e"));
+        assert_eq!(undone(&stacks), vec![(Kind::Delete, 4, "de")]);
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut stacks = Stacks::default();
+        typed(&mut stacks, 0, "a");
+        let edit = stacks.undo.pop().unwrap();
+        stacks.redo.push(edit);
+        stacks.applying = true;
+        typed(&mut stacks, 0, "ignored");
+        stacks.applying = false;
+        assert!(stacks.undo.is_empty());
+        assert_eq!(stacks.redo.len(), 1);
+        typed(&mut stacks, 0, "b");
+        assert!(stacks.redo.is_empty());
+    }
+}
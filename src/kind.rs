@@ -0,0 +1,500 @@
+use {
+    crate::{
+        currency,
+        preprocess::{self, Token},
+    },
+    evalexpr::{EvalexprError, EvalexprResult},
+    std::collections::HashMap,
+};
+
+/// What a number stands for. Dates, durations and amounts of money are plain numbers to the
+/// evaluator, so they work with every operator, and their kind is worked out from the
+/// expressions they come from to show results as dates, durations and amounts in a currency.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    /// A number known to be just a number, like a literal
+    Plain,
+    /// Seconds since 1970-01-01 00:00 UTC
+    Date,
+    /// Seconds
+    Duration,
+    /// An amount in the currency with this code
+    Money(String),
+    /// Anything else, including numbers of unknown origin
+    Unknown,
+}
+
+/// Functions returning dates or durations
+const FUNCTIONS: &[(&str, Kind)] = &[
+    ("date::parse", Kind::Date),
+    ("date::now", Kind::Date),
+    ("date::today", Kind::Date),
+    ("date::add_days", Kind::Date),
+    ("date::add_months", Kind::Date),
+    ("date::add_business_days", Kind::Date),
+    ("date::span", Kind::Duration),
+];
+
+/// Operators of more than one character, longest first
+const OPERATORS: &[&str] = &[
+    "&&=", "||=", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "^=",
+];
+
+/// Assignments applying an operator
+const ASSIGNMENTS: &[(&str, &str)] = &[
+    ("+=", "+"),
+    ("-=", "-"),
+    ("*=", "*"),
+    ("/=", "/"),
+    ("%=", "%"),
+    ("^=", "^"),
+];
+
+/// Calls arithmetic operators are rewritten into in programmer mode
+const BIT_ARITHMETIC: &[(&str, &str)] = &[
+    ("bit::add", "+"),
+    ("bit::sub", "-"),
+    ("bit::mul", "*"),
+    ("bit::div", "/"),
+    ("bit::rem", "%"),
+];
+
+fn error<T>(message: String) -> EvalexprResult<T> {
+    Err(EvalexprError::CustomMessage(message))
+}
+
+/// The kind of the result of `op` applied to numbers of kinds `a` and `b`
+fn combine(op: &str, a: &Kind, b: &Kind) -> EvalexprResult<Kind> {
+    use Kind::*;
+    Ok(match (op, a, b) {
+        (_, Plain, Plain) => Plain,
+        ("+", Money(x), Money(y)) | ("-", Money(x), Money(y)) if x != y => {
+            return error(format!(
+                "Can not mix amounts in {} and {}, convert one of them with \"in {}\"",
+                x, y, x
+            ))
+        }
+        ("+", Money(_), Plain) | ("-", Money(_), Plain) => {
+            return error(format!(
+                "Can not mix amounts with and without a currency, write it like 10 {}",
+                code(a)
+            ))
+        }
+        ("+", Plain, Money(_)) | ("-", Plain, Money(_)) => {
+            return error(format!(
+                "Can not mix amounts with and without a currency, write it like 10 {}",
+                code(b)
+            ))
+        }
+        ("+", Money(x), Money(_)) | ("-", Money(x), Money(_)) => Money(x.clone()),
+        ("*", Money(x), Plain) | ("*", Plain, Money(x)) | ("/", Money(x), Plain) => {
+            Money(x.clone())
+        }
+        ("/", Money(x), Money(y)) if x == y => Plain,
+        ("+", Date, Duration) | ("+", Duration, Date) | ("-", Date, Duration) => Date,
+        ("-", Date, Date) => Duration,
+        ("+", Duration, Duration) | ("-", Duration, Duration) => Duration,
+        ("*", Duration, Plain) | ("*", Plain, Duration) | ("/", Duration, Plain) => Duration,
+        ("/", Duration, Duration) => Plain,
+        _ => Unknown,
+    })
+}
+
+/// The currency code of a `Money` kind
+fn code(kind: &Kind) -> &str {
+    match kind {
+        Kind::Money(code) => code,
+        _ => "",
+    }
+}
+
+/// The tokens of `source` without spaces, with operators like `+=` joined into one
+fn operators(source: &str) -> Vec<Token> {
+    let tokens = preprocess::tokenize(source);
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let joined = OPERATORS.iter().find(|op| {
+            tokens.len() >= i + op.len()
+                && tokens[i..i + op.len()]
+                    .iter()
+                    .map(Token::text)
+                    .collect::<String>()
+                    == **op
+        });
+        match (joined, tokens[i]) {
+            (Some(op), _) => {
+                out.push(Token::Punct(op));
+                i += op.len();
+            }
+            (None, Token::Space(_)) => i += 1,
+            (None, token) => {
+                out.push(token);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// The kinds of the variables of a document, as statements assign them
+#[derive(Debug, Clone, Default)]
+pub struct Kinds {
+    variables: HashMap<String, Kind>,
+    /// Code of the base currency of the rates file, what `cur::from` converts to
+    base: String,
+}
+
+impl Kinds {
+    pub fn new() -> Self {
+        Self {
+            variables: HashMap::new(),
+            base: currency::rates()
+                .ok()
+                .and_then(|rates| rates.base.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The kind of the value of the prepared expression `source`, recording the kinds of the
+    /// variables it assigns
+    pub fn infer(&mut self, source: &str) -> EvalexprResult<Kind> {
+        let tokens = operators(source);
+        Parser {
+            tokens: &tokens,
+            pos: 0,
+            kinds: self,
+        }
+        .chain()
+    }
+
+    /// Record the kind of the variable `name`, for variables set outside of expressions
+    pub fn set(&mut self, name: &str, kind: Kind) {
+        self.variables.insert(name.to_string(), kind);
+    }
+
+    fn get(&self, name: &str) -> Kind {
+        self.variables.get(name).cloned().unwrap_or(Kind::Unknown)
+    }
+
+    /// The kind of what the function `name` returns for arguments of kinds `args`, along with
+    /// the text of those that are string literals
+    fn function(&self, name: &str, args: &[(Kind, Option<&str>)]) -> EvalexprResult<Kind> {
+        let money = |code: &str| Kind::Money(code.to_uppercase());
+        let arithmetic = BIT_ARITHMETIC
+            .iter()
+            .find(|(function, _)| *function == name);
+        if let (Some((_, op)), [(a, _), (b, _)]) = (arithmetic, args) {
+            return combine(op, a, b);
+        }
+        Ok(match (name, args) {
+            // Percentages of an amount are amounts of the same kind
+            ("pct::add", [(x, _), _]) | ("pct::sub", [(x, _), _]) | ("pct::of", [_, (x, _)]) => {
+                x.clone()
+            }
+            // Calendar days added to a length of time, `2 hours + 1 day`
+            ("date::add_days", [(Kind::Duration, _), ..])
+            | ("date::add_months", [(Kind::Duration, _), ..]) => Kind::Duration,
+            ("cur::from", [(Kind::Money(x), _), (_, Some(code))]) => {
+                return error(format!(
+                    "Can not take an amount in {} as one in {}, use cur::convert",
+                    x, code
+                ))
+            }
+            ("cur::from", [_, (_, Some(_))]) => Kind::Money(self.base.clone()),
+            ("cur::to", [(Kind::Money(x), _), (_, Some(code))]) if *x != self.base => {
+                return error(format!(
+                    "Can not convert an amount in {} to {} as if it was in the base currency {}, \
+                     use cur::convert",
+                    x, code, self.base
+                ))
+            }
+            ("cur::to", [_, (_, Some(code))]) | ("cur::convert", [_, _, (_, Some(code))]) => {
+                money(code)
+            }
+            _ => FUNCTIONS
+                .iter()
+                .find(|(function, _)| *function == name)
+                .map_or(Kind::Unknown, |(_, kind)| kind.clone()),
+        })
+    }
+}
+
+/// Follows the precedence of evalexpr's operators, only as far as needed to tell kinds apart.
+/// Syntax errors are left for evalexpr to report, what can not be parsed is `Unknown`.
+struct Parser<'t, 'a, 'k> {
+    tokens: &'t [Token<'a>],
+    pos: usize,
+    kinds: &'k mut Kinds,
+}
+
+impl<'t, 'a, 'k> Parser<'t, 'a, 'k> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    /// The operator at the current token if it is one of `ops`, skipping it
+    fn next_of(&mut self, ops: &[&str]) -> Option<&'a str> {
+        match self.peek() {
+            Some(Token::Punct(p)) if ops.contains(&p) => {
+                self.pos += 1;
+                Some(p)
+            }
+            _ => None,
+        }
+    }
+
+    /// `a; b`, the kind of the last statement
+    fn chain(&mut self) -> EvalexprResult<Kind> {
+        let mut kind = self.assignment()?;
+        while self.next_of(&[";"]).is_some() {
+            kind = match self.peek() {
+                None | Some(Token::Punct(")")) => Kind::Unknown,
+                _ => self.assignment()?,
+            };
+        }
+        Ok(kind)
+    }
+
+    /// `a = b` and `a += b`, which give nothing but set the kind of `a`
+    fn assignment(&mut self) -> EvalexprResult<Kind> {
+        let (name, op) = match (self.peek(), self.tokens.get(self.pos + 1)) {
+            (Some(Token::Ident(name)), Some(&Token::Punct(op)))
+                if op == "=" || ASSIGNMENTS.iter().any(|(a, _)| *a == op) =>
+            {
+                (name, op)
+            }
+            _ => return self.tuple(),
+        };
+        self.pos += 2;
+        let value = self.assignment()?;
+        let kind = match ASSIGNMENTS.iter().find(|(a, _)| *a == op) {
+            Some((_, op)) => combine(op, &self.kinds.get(name), &value)?,
+            None => value,
+        };
+        self.kinds.set(name, kind);
+        Ok(Kind::Unknown)
+    }
+
+    /// `a, b`, the kinds of the elements
+    fn elements(&mut self) -> EvalexprResult<Vec<Kind>> {
+        let mut kinds = vec![self.comparison()?];
+        while self.next_of(&[","]).is_some() {
+            kinds.push(self.comparison()?);
+        }
+        Ok(kinds)
+    }
+
+    fn tuple(&mut self) -> EvalexprResult<Kind> {
+        let mut kinds = self.elements()?;
+        Ok(match kinds.len() {
+            1 => kinds.remove(0),
+            _ => Kind::Unknown,
+        })
+    }
+
+    /// Logical and comparison operators, none of them give numbers
+    fn comparison(&mut self) -> EvalexprResult<Kind> {
+        let ops = ["||", "&&", "==", "!=", "<", ">", "<=", ">="];
+        let mut kind = self.sum()?;
+        while self.next_of(&ops).is_some() {
+            self.sum()?;
+            kind = Kind::Unknown;
+        }
+        Ok(kind)
+    }
+
+    fn sum(&mut self) -> EvalexprResult<Kind> {
+        let mut kind = self.product()?;
+        while let Some(op) = self.next_of(&["+", "-"]) {
+            let rhs = self.product()?;
+            kind = combine(op, &kind, &rhs)?;
+        }
+        Ok(kind)
+    }
+
+    fn product(&mut self) -> EvalexprResult<Kind> {
+        let mut kind = self.unary()?;
+        while let Some(op) = self.next_of(&["*", "/", "%"]) {
+            let rhs = self.unary()?;
+            kind = combine(op, &kind, &rhs)?;
+        }
+        Ok(kind)
+    }
+
+    fn unary(&mut self) -> EvalexprResult<Kind> {
+        match self.next_of(&["-", "!"]) {
+            Some("-") => self.unary(),
+            Some(_) => self.unary().map(|_| Kind::Unknown),
+            None => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> EvalexprResult<Kind> {
+        let kind = self.primary()?;
+        match self.next_of(&["^"]) {
+            Some(op) => combine(op, &kind, &self.unary()?),
+            None => Ok(kind),
+        }
+    }
+
+    fn primary(&mut self) -> EvalexprResult<Kind> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Ok(Kind::Unknown),
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(_) => Ok(Kind::Plain),
+            Token::Ident(name) if self.next_of(&["("]).is_some() => {
+                let args = self.arguments()?;
+                self.close();
+                self.kinds.function(name, &args)
+            }
+            Token::Ident(name) => Ok(self.kinds.get(name)),
+            Token::Punct("(") => {
+                let kind = match self.peek() {
+                    Some(Token::Punct(")")) => Kind::Unknown,
+                    _ => self.chain()?,
+                };
+                self.close();
+                Ok(kind)
+            }
+            _ => Ok(Kind::Unknown),
+        }
+    }
+
+    /// The arguments of a function call, with the text of those that are string literals
+    fn arguments(&mut self) -> EvalexprResult<Vec<(Kind, Option<&'a str>)>> {
+        let mut args = Vec::new();
+        if let Some(Token::Punct(")")) = self.peek() {
+            return Ok(args);
+        }
+        loop {
+            let literal = match (self.peek(), self.tokens.get(self.pos + 1)) {
+                (Some(Token::Str(s)), Some(Token::Punct(",")))
+                | (Some(Token::Str(s)), Some(Token::Punct(")"))) => Some(s.trim_matches('"')),
+                _ => None,
+            };
+            args.push((self.comparison()?, literal));
+            if self.next_of(&[","]).is_none() {
+                return Ok(args);
+            }
+        }
+    }
+
+    /// Skip to after the `)` closing the bracket the parser is in
+    fn close(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            self.pos += 1;
+            match token {
+                Token::Punct("(") => depth += 1,
+                Token::Punct(")") if depth == 0 => return,
+                Token::Punct(")") => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds() -> Kinds {
+        Kinds {
+            base: String::from("EUR"),
+            ..Kinds::default()
+        }
+    }
+
+    fn kind(source: &str) -> Kind {
+        kinds().infer(source).unwrap()
+    }
+
+    fn mixing(source: &str) -> bool {
+        kinds().infer(source).is_err()
+    }
+
+    #[test]
+    fn dates_and_durations() {
+        assert_eq!(kind("date::parse(\"2024-01-31\")"), Kind::Date);
+        assert_eq!(
+            kind("date::parse(\"2024-01-31\") + date::span(30 * 86400)"),
+            Kind::Date
+        );
+        assert_eq!(kind("date::now() - date::today()"), Kind::Duration);
+        assert_eq!(kind("-date::span(60) * 2"), Kind::Duration);
+        assert_eq!(kind("date::span(60) / date::span(30)"), Kind::Plain);
+        assert_eq!(kind("date::span(60) * date::span(30)"), Kind::Unknown);
+        assert_eq!(kind("(1 + 2) * 3"), Kind::Plain);
+        assert_eq!(kind("date::today() <= date::now()"), Kind::Unknown);
+        assert_eq!(kind("date::year(date::now())"), Kind::Unknown);
+        assert_eq!(kind("date::add_days(date::today(), 30)"), Kind::Date);
+        assert_eq!(
+            kind("date::add_days(date::span(7200), 1, \"UTC\")"),
+            Kind::Duration
+        );
+    }
+
+    #[test]
+    fn variables_keep_their_kind() {
+        let mut kinds = kinds();
+        assert_eq!(
+            kinds
+                .infer("d = date::today(); d += date::span(3600); d")
+                .unwrap(),
+            Kind::Date
+        );
+        assert_eq!(kinds.infer("d - date::today()").unwrap(), Kind::Duration);
+        assert_eq!(kinds.infer("d == d").unwrap(), Kind::Unknown);
+        assert_eq!(kinds.infer("x").unwrap(), Kind::Unknown);
+    }
+
+    #[test]
+    fn programmer_mode_arithmetic_keeps_kinds() {
+        assert_eq!(
+            kind("bit::add(date::today(), date::span(bit::mul(2, 60)))"),
+            Kind::Date
+        );
+        assert_eq!(kind("bit::sub(date::now(), date::today())"), Kind::Duration);
+        assert_eq!(kind("bit::add(1, 2)"), Kind::Plain);
+        assert_eq!(kind("bit::and(date::span(60), 1)"), Kind::Unknown);
+        assert!(mixing("bit::add(cur::from(1, \"USD\"), 2)"));
+    }
+
+    #[test]
+    fn percentages_keep_the_kind_of_the_amount() {
+        assert_eq!(kind("pct::add(date::span(100), 10)"), Kind::Duration);
+        assert_eq!(kind("pct::of(10, date::span(100))"), Kind::Duration);
+    }
+
+    #[test]
+    fn money_keeps_its_currency() {
+        let eur = Kind::Money(String::from("EUR"));
+        let usd = Kind::Money(String::from("USD"));
+        assert_eq!(kind("cur::from(100, \"USD\")"), eur);
+        assert_eq!(kind("cur::from(100, \"USD\") + cur::from(5, \"GBP\")"), eur);
+        assert_eq!(kind("cur::to(cur::from(100, \"GBP\"), \"usd\")"), usd);
+        assert_eq!(kind("cur::convert(100, \"GBP\", \"USD\") * 3"), usd);
+        assert_eq!(kind("cur::to(100, \"USD\") / 4"), usd);
+        assert_eq!(
+            kind("cur::from(10, \"USD\") / cur::from(5, \"USD\")"),
+            Kind::Plain
+        );
+        assert_eq!(kind("pct::sub(cur::from(100, \"USD\"), 20)"), eur);
+    }
+
+    #[test]
+    fn money_does_not_mix() {
+        assert!(mixing("cur::from(100, \"USD\") + 5"));
+        assert!(mixing("5 - cur::from(100, \"USD\")"));
+        assert!(mixing("cur::to(100, \"USD\") + cur::to(100, \"GBP\")"));
+        assert!(mixing("cur::to(cur::to(100, \"USD\"), \"GBP\")"));
+        assert!(mixing("cur::from(cur::from(100, \"USD\"), \"GBP\")"));
+        assert!(mixing("a = cur::from(1, \"USD\"); a += 1"));
+        assert!(!mixing("cur::from(100, \"USD\") + x"));
+        assert!(!mixing("cur::from(100, \"USD\") * 5"));
+    }
+}
@@ -1,17 +1,43 @@
 use {
+    angle::AngleUnit,
     error::MEEResult,
-    gio::{prelude::*, ApplicationFlags, Resource, SimpleAction},
+    eval::EvalOptions,
+    gio::{prelude::*, ApplicationFlags, Resource},
     glib::{clone, Bytes},
     gtk::{
         prelude::*, Application, CssProvider, CssProviderExt, StyleContext,
         STYLE_PROVIDER_PRIORITY_APPLICATION,
     },
-    std::fs,
     ui::Ui,
 };
 
+mod angle;
+mod builtins;
+mod calculus;
+mod closure;
+mod currency;
+mod dates;
 mod error;
+mod eval;
+mod finance;
+mod format;
+mod header;
+mod history;
+mod kind;
 mod macros;
+mod percent;
+mod plot;
+mod preferences;
+mod preprocess;
+mod programmer;
+mod recent;
+mod recovery;
+mod report;
+mod sequence;
+mod solver;
+mod stats;
+mod symbolic;
+mod textfile;
 mod ui;
 
 const RESOURCE_BYTES: &[u8] = include_bytes!("../out/mathexpreval.gresource");
@@ -41,9 +67,16 @@ fn main() -> MEEResult<()> {
     // app.set_accels_for_action("result.copy", &["<CTRL>C"]);
     app.set_accels_for_action("file.open", &["<CTRL>O"]);
     app.set_accels_for_action("file.save", &["<CTRL>S"]);
+    app.set_accels_for_action("file.save-as", &["<CTRL><SHIFT>S"]);
+    app.set_accels_for_action("edit.undo", &["<CTRL>Z"]);
+    app.set_accels_for_action("edit.redo", &["<CTRL><SHIFT>Z"]);
     app.set_accels_for_action("app.math", &["<CTRL>M"]);
+    app.set_accels_for_action("app.plot", &["<CTRL>P"]);
     app.set_accels_for_action("app.help", &["<CTRL>H"]);
-    app.set_accels_for_action("app.quit", &["<CTRL>Q", "<CTRL>W"]);
+    app.set_accels_for_action("app.eval", &["<CTRL>Return"]);
+    app.set_accels_for_action("app.preferences", &["<CTRL>comma"]);
+    app.set_accels_for_action("win.close", &["<CTRL>W"]);
+    app.set_accels_for_action("app.quit", &["<CTRL>Q"]);
 
     app.add_main_option(
         "eval",
@@ -53,29 +86,72 @@ fn main() -> MEEResult<()> {
         "Run file",
         Some("FILE"),
     );
+    app.add_main_option(
+        "rates",
+        glib::Char::new('r').unwrap(),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Filename,
+        "Read currency rates from FILE, a .csv or .json file",
+        Some("FILE"),
+    );
+    app.add_main_option(
+        "angle",
+        glib::Char::new('a').unwrap(),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Evaluate with angles in UNIT, rad, deg or grad, unless the file sets its own",
+        Some("UNIT"),
+    );
 
     // Load settings
     let settings = gio::Settings::new("net.olback.MathExprEval");
 
+    // `math-expr-eval recent` lists recent documents, pinned ones marked with a star
+    let args = std::env::args().collect::<Vec<String>>();
+    if args.get(1).map(String::as_str) == Some("recent") {
+        for document in recent::documents(&settings) {
+            println!(
+                "{} {}",
+                if document.pinned { '*' } else { ' ' },
+                document.path.display()
+            );
+        }
+        return Ok(());
+    }
+
     // Create ui
     let ui_ref = Ui::new(&settings);
 
     // Handle args
-    app.connect_handle_local_options(|_, dict| {
-        if let Some(path) = dict
-            .lookup_value("eval", None)
-            .map(|v| v.get_data_as_bytes())
-            .and_then(|b| {
-                std::str::from_utf8(&(*b)[..b.len() - 1])
-                    .ok()
-                    .map(|s| s.trim().to_string())
-            })
-        {
-            match fs::read_to_string(path) {
-                Ok(content) => match evalexpr::eval_with_context_mut(
-                    &content,
-                    &mut evalexpr::math_consts_context!().unwrap(),
-                ) {
+    app.connect_handle_local_options(clone!(@strong settings => move |_, dict| {
+        let filename = |name| {
+            dict.lookup_value(name, None)
+                .map(|v| v.get_data_as_bytes())
+                .and_then(|b| {
+                    std::str::from_utf8(&(*b)[..b.len() - 1])
+                        .ok()
+                        .map(|s| s.trim().to_string())
+                })
+        };
+
+        if let Some(path) = filename("rates") {
+            currency::set_path(path.into());
+        }
+
+        let mut options = EvalOptions::from_settings(&settings);
+        if let Some(unit) = dict.lookup_value("angle", None) {
+            match unit.get_str().and_then(AngleUnit::from_name) {
+                Some(angle) => options.angle = angle,
+                None => {
+                    eprintln!("Unknown angle unit, expected rad, deg or grad");
+                    return 1;
+                }
+            }
+        }
+
+        if let Some(path) = filename("eval") {
+            match textfile::load(&gio::File::new_for_path(path)) {
+                Ok(text) => match eval::eval_to_string(&text.content, options) {
                     Ok(result) => println!("{}", result),
                     Err(e) => eprintln!("{}", e),
                 },
@@ -85,63 +161,23 @@ fn main() -> MEEResult<()> {
         } else {
             -1
         }
-    });
-
-    // Handle when the app is run with a file
-    app.connect_open(glib::clone!(@strong ui_ref => move |app, files, _| {
-        if files.len() == 1 {
-            const C: Option<&'static gio::Cancellable> = None;
-            let mut buf = [0u8; 1024 * 1024];
-            let content_bytes = files[0].read(C).and_then(|stream| stream.read_all(&mut buf, C)).map(|(len, _)| &buf[0..len]);
-            match content_bytes {
-                Ok(bytes) => match std::str::from_utf8(bytes) {
-                    Ok(s) => {
-                        ui_ref.set_input(s);
-                        if let Some(path) = files[0].get_path() {
-                            ui_ref.set_path(path);
-                        }
-                        ui_ref.set_app(app);
-                        ui_ref.show();
-                    },
-                    Err(e) => eprint!("{}", e)
-                },
-                Err(e) => eprintln!("{}", e)
-            }
-        } else {
-            eprintln!("Expected 1 file, got {}", files.len());
-        }
     }));
 
-    let app_ag = ui_ref.new_action_group("app");
-
-    let to_math_action = SimpleAction::new("math", None);
-    to_math_action.connect_activate(clone!(@strong ui_ref => move |_, _| {
-        ui_ref.show_math();
-    }));
-    app_ag.add_action(&to_math_action);
-
-    let to_help_action = SimpleAction::new("help", None);
-    to_help_action.connect_activate(clone!(@strong ui_ref => move |_, _| {
-        ui_ref.show_help();
-    }));
-    app_ag.add_action(&to_help_action);
-
-    let quit_action = SimpleAction::new("quit", None);
-    quit_action.connect_activate(clone!(@strong app => move |_, _| {
-        app.quit();
+    // Handle when the app is run with files, each gets its own window
+    app.connect_open(glib::clone!(@strong ui_ref => move |app, files, _| {
+        ui_ref.set_app(app);
+        ui_ref.show();
+        ui_ref.open_all(files);
     }));
-    app_ag.add_action(&quit_action);
 
     app.connect_activate(clone!(@strong ui_ref => move |app| {
         ui_ref.set_app(app);
         ui_ref.show();
+        ui_ref.restore_snapshots();
+        ui_ref.show_start();
     }));
 
-    app.connect_shutdown(move |_| {
-        ui_ref.quit();
-    });
-
-    app.run(&std::env::args().collect::<Vec<String>>());
+    app.run(&args);
 
     Ok(())
 }
@@ -0,0 +1,45 @@
+use evalexpr::{EvalexprError, EvalexprResult, Value};
+
+/// The two numeric arguments of the percentage functions
+fn two(value: &Value) -> EvalexprResult<(f64, f64)> {
+    match value {
+        Value::Tuple(t) if t.len() == 2 => Ok((t[0].as_number()?, t[1].as_number()?)),
+        Value::Tuple(t) => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 2,
+            actual: t.len(),
+        }),
+        _ => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 2,
+            actual: 1,
+        }),
+    }
+}
+
+/// `pct::of(p, x)`, `p` percent of `x`. `p% of x` is rewritten into this, and `p%` on its own
+/// into `pct::of(p, 1)`.
+pub fn of(value: &Value) -> EvalexprResult<Value> {
+    let (p, x) = two(value)?;
+    Ok(Value::Float(p * x / 100.0))
+}
+
+/// `pct::add(x, p)`, `x` increased by `p` percent, what `x + p%` is rewritten into
+pub fn add(value: &Value) -> EvalexprResult<Value> {
+    let (x, p) = two(value)?;
+    Ok(Value::Float(x + x * p / 100.0))
+}
+
+/// `pct::sub(x, p)`, `x` decreased by `p` percent, what `x - p%` is rewritten into
+pub fn sub(value: &Value) -> EvalexprResult<Value> {
+    let (x, p) = two(value)?;
+    Ok(Value::Float(x - x * p / 100.0))
+}
+
+/// `pct::ratio(x, y)`, how many percent of `y` `x` is, what `x as % of y` is rewritten into
+pub fn ratio(value: &Value) -> EvalexprResult<Value> {
+    match two(value)? {
+        (_, y) if y == 0.0 => Err(EvalexprError::CustomMessage(String::from(
+            "Can not express a value as a percentage of 0",
+        ))),
+        (x, y) => Ok(Value::Float(x * 100.0 / y)),
+    }
+}
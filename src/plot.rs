@@ -0,0 +1,426 @@
+use {
+    crate::{
+        eval::{self, EvalOptions},
+        get_obj,
+    },
+    cairo::{Context, Format, ImageSurface, SvgSurface},
+    evalexpr::{ContextWithMutableVariables, HashMapContext, Value},
+    glib::clone,
+    gtk::{
+        prelude::*, Builder, Button, CheckButton, DrawingArea, Entry, Inhibit, Label, SpinButton,
+    },
+    std::{cell::RefCell, fs::File, path::Path, rc::Rc},
+};
+
+const COLORS: &[(f64, f64, f64)] = &[
+    (0.21, 0.52, 0.89),
+    (0.88, 0.11, 0.14),
+    (0.20, 0.82, 0.48),
+    (0.96, 0.47, 0.00),
+    (0.57, 0.25, 0.67),
+];
+
+#[derive(Debug, Clone, Copy)]
+struct View {
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl View {
+    fn to_screen(&self, x: f64, y: f64, width: f64, height: f64) -> (f64, f64) {
+        (
+            (x - self.x_min) / (self.x_max - self.x_min) * width,
+            height - (y - self.y_min) / (self.y_max - self.y_min) * height,
+        )
+    }
+
+    fn to_plot(&self, sx: f64, sy: f64, width: f64, height: f64) -> (f64, f64) {
+        (
+            self.x_min + sx / width * (self.x_max - self.x_min),
+            self.y_min + (height - sy) / height * (self.y_max - self.y_min),
+        )
+    }
+}
+
+type Curve = Vec<(f64, f64)>;
+
+#[derive(Debug)]
+pub struct Plot {
+    pub export_png_button: Button,
+    pub export_svg_button: Button,
+    reset_button: Button,
+    area: DrawingArea,
+    expressions: Entry,
+    x_from: SpinButton,
+    x_to: SpinButton,
+    grid: CheckButton,
+    cursor_label: Label,
+    source: RefCell<String>,
+    options: RefCell<EvalOptions>,
+    /// The document evaluated with `options`, `None` until it is needed after a change
+    context: RefCell<Option<HashMapContext>>,
+    view: RefCell<View>,
+    drag: RefCell<Option<(f64, f64)>>,
+    syncing: RefCell<bool>,
+}
+
+impl Plot {
+    pub fn new(b: &Builder) -> Rc<Self> {
+        let this = Rc::new(Self {
+            export_png_button: get_obj!(b, "plot-export-png-button"),
+            export_svg_button: get_obj!(b, "plot-export-svg-button"),
+            reset_button: get_obj!(b, "plot-reset-button"),
+            area: get_obj!(b, "plot-area"),
+            expressions: get_obj!(b, "plot-expressions"),
+            x_from: get_obj!(b, "plot-x-from"),
+            x_to: get_obj!(b, "plot-x-to"),
+            grid: get_obj!(b, "plot-grid"),
+            cursor_label: get_obj!(b, "plot-cursor-label"),
+            source: RefCell::new(String::new()),
+            options: RefCell::new(EvalOptions::default()),
+            context: RefCell::new(None),
+            view: RefCell::new(View {
+                x_min: -10.0,
+                x_max: 10.0,
+                y_min: -10.0,
+                y_max: 10.0,
+            }),
+            drag: RefCell::new(None),
+            syncing: RefCell::new(false),
+        });
+
+        this.area
+            .connect_draw(clone!(@strong this => move |area, cr| {
+                let width = area.get_allocated_width() as f64;
+                let height = area.get_allocated_height() as f64;
+                if let Err(e) = this.draw(cr, width, height) {
+                    this.cursor_label.set_text(&e);
+                }
+                Inhibit(false)
+            }));
+
+        // Pan
+        this.area
+            .connect_button_press_event(clone!(@strong this => move |_, evt| {
+                if evt.get_button() == 1 {
+                    this.drag.replace(Some(evt.get_position()));
+                }
+                Inhibit(false)
+            }));
+        this.area
+            .connect_button_release_event(clone!(@strong this => move |_, _| {
+                this.drag.replace(None);
+                Inhibit(false)
+            }));
+        this.area
+            .connect_motion_notify_event(clone!(@strong this => move |area, evt| {
+                let (width, height) = this.size();
+                let (sx, sy) = evt.get_position();
+                let last = *this.drag.borrow();
+                if let Some((lx, ly)) = last {
+                    let mut view = this.view.borrow_mut();
+                    let dx = (sx - lx) / width * (view.x_max - view.x_min);
+                    let dy = (sy - ly) / height * (view.y_max - view.y_min);
+                    view.x_min -= dx;
+                    view.x_max -= dx;
+                    view.y_min += dy;
+                    view.y_max += dy;
+                    drop(view);
+                    this.drag.replace(Some((sx, sy)));
+                    this.sync_range();
+                    area.queue_draw();
+                }
+                let (x, y) = this.view.borrow().to_plot(sx, sy, width, height);
+                this.cursor_label.set_text(&format!("x = {:.4}, y = {:.4}", x, y));
+                Inhibit(false)
+            }));
+
+        // Zoom around the cursor
+        this.area
+            .connect_scroll_event(clone!(@strong this => move |area, evt| {
+                let factor = match evt.get_direction() {
+                    gdk::ScrollDirection::Up => 0.9,
+                    gdk::ScrollDirection::Down => 1.1,
+                    gdk::ScrollDirection::Smooth => 1.0 + evt.get_delta().1.clamp(-1.0, 1.0) * 0.1,
+                    _ => return Inhibit(false),
+                };
+                let (width, height) = this.size();
+                let (sx, sy) = evt.get_position();
+                let mut view = this.view.borrow_mut();
+                let (px, py) = view.to_plot(sx, sy, width, height);
+                view.x_min = px - (px - view.x_min) * factor;
+                view.x_max = px + (view.x_max - px) * factor;
+                view.y_min = py - (py - view.y_min) * factor;
+                view.y_max = py + (view.y_max - py) * factor;
+                drop(view);
+                this.sync_range();
+                area.queue_draw();
+                Inhibit(true)
+            }));
+
+        this.expressions
+            .connect_changed(clone!(@strong this => move |_| {
+                this.reset_view();
+            }));
+
+        this.x_from
+            .connect_value_changed(clone!(@strong this => move |_| {
+                this.range_changed();
+            }));
+        this.x_to
+            .connect_value_changed(clone!(@strong this => move |_| {
+                this.range_changed();
+            }));
+
+        this.grid.connect_toggled(clone!(@strong this => move |_| {
+            this.area.queue_draw();
+        }));
+
+        this.reset_button
+            .connect_clicked(clone!(@strong this => move |_| {
+                let mut view = this.view.borrow_mut();
+                view.x_min = -10.0;
+                view.x_max = 10.0;
+                drop(view);
+                this.sync_range();
+                this.reset_view();
+            }));
+
+        this
+    }
+
+    /// Document whose variables are available to the plotted expressions
+    pub fn set_source(&self, source: &str, options: EvalOptions) {
+        if *self.source.borrow() == source && *self.options.borrow() == options {
+            return;
+        }
+        self.source.replace(source.to_string());
+        self.options.replace(options);
+        self.context.replace(None);
+        self.area.queue_draw();
+    }
+
+    /// Fit the y axis to the curves in the current x range
+    pub fn reset_view(&self) {
+        let mut view = *self.view.borrow();
+        if let Ok(curves) = self.sample(&view, 200) {
+            let mut ys = curves
+                .iter()
+                .flatten()
+                .map(|(_, y)| *y)
+                .filter(|y| y.is_finite())
+                .collect::<Vec<_>>();
+            if !ys.is_empty() {
+                // Ignore the outermost values so asymptotes don't flatten everything else
+                ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let lo = ys[ys.len() / 50];
+                let hi = ys[ys.len() - 1 - ys.len() / 50];
+                let margin = ((hi - lo) * 0.1).max(1e-9);
+                view.y_min = lo - margin;
+                view.y_max = hi + margin;
+                if hi - lo < 1e-9 {
+                    view.y_min -= 1.0;
+                    view.y_max += 1.0;
+                }
+            }
+        }
+        self.view.replace(view);
+        self.area.queue_draw();
+    }
+
+    pub fn export_png(&self, path: &Path) -> Result<(), String> {
+        let (width, height) = self.size();
+        let surface = ImageSurface::create(Format::ARgb32, width as i32, height as i32)
+            .map_err(|e| e.to_string())?;
+        self.draw(&Context::new(&surface), width, height)?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
+        surface.write_to_png(&mut file).map_err(|e| e.to_string())
+    }
+
+    pub fn export_svg(&self, path: &Path) -> Result<(), String> {
+        let (width, height) = self.size();
+        let surface = SvgSurface::new(width, height, Some(path)).map_err(|e| e.to_string())?;
+        self.draw(&Context::new(&surface), width, height)?;
+        surface.finish();
+        Ok(())
+    }
+
+    fn size(&self) -> (f64, f64) {
+        (
+            self.area.get_allocated_width().max(1) as f64,
+            self.area.get_allocated_height().max(1) as f64,
+        )
+    }
+
+    fn range_changed(&self) {
+        if *self.syncing.borrow() {
+            return;
+        }
+        let (from, to) = (self.x_from.get_value(), self.x_to.get_value());
+        if from < to {
+            let mut view = self.view.borrow_mut();
+            view.x_min = from;
+            view.x_max = to;
+            drop(view);
+            self.reset_view();
+        }
+    }
+
+    fn sync_range(&self) {
+        let view = *self.view.borrow();
+        self.syncing.replace(true);
+        self.x_from.set_value(view.x_min);
+        self.x_to.set_value(view.x_max);
+        self.syncing.replace(false);
+    }
+
+    fn sample(&self, view: &View, steps: usize) -> Result<Vec<Curve>, String> {
+        let source = self.source.borrow();
+        let options = self.options.borrow().with_directives(&source);
+        // The document is evaluated on the first draw after it changed, not on every redraw
+        // while panning, nor on every keystroke while the plot page is hidden
+        let mut context = self.context.borrow_mut();
+        let context = context.get_or_insert_with(|| {
+            eval::eval_with_context(&source, options)
+                .map(|(_, context)| context)
+                .unwrap_or_else(|_| eval::context(&options))
+        });
+
+        self.expressions
+            .get_text()
+            .split(';')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(|expr| {
+                let node = evalexpr::build_operator_tree(&eval::prepare(expr, &options))
+                    .map_err(|e| format!("{}: {}", expr, e))?;
+                let mut first_error = None;
+                let curve = (0..=steps)
+                    .map(|i| {
+                        let x = view.x_min + (view.x_max - view.x_min) * i as f64 / steps as f64;
+                        let y = context
+                            .set_value("x".into(), Value::Float(x))
+                            .and_then(|_| node.eval_with_context(&*context))
+                            .and_then(|v| v.as_number());
+                        match y {
+                            Ok(y) => (x, y),
+                            Err(e) => {
+                                first_error.get_or_insert(e);
+                                (x, f64::NAN)
+                            }
+                        }
+                    })
+                    .collect::<Curve>();
+                match first_error {
+                    Some(e) if !curve.iter().any(|(_, y)| y.is_finite()) => {
+                        Err(format!("{}: {}", expr, e))
+                    }
+                    _ => Ok(curve),
+                }
+            })
+            .collect()
+    }
+
+    fn draw(&self, cr: &Context, width: f64, height: f64) -> Result<(), String> {
+        let view = *self.view.borrow();
+
+        cr.set_source_rgb(1.0, 1.0, 1.0);
+        cr.paint();
+
+        cr.set_font_size(11.0);
+        cr.set_line_width(1.0);
+
+        let x_ticks = ticks(view.x_min, view.x_max, width);
+        let y_ticks = ticks(view.y_min, view.y_max, height);
+        let (origin_x, origin_y) = view.to_screen(0.0, 0.0, width, height);
+        let axis_x = origin_x.max(0.0).min(width - 1.0);
+        let axis_y = origin_y.max(0.0).min(height - 1.0);
+
+        // Grid
+        if self.grid.get_active() {
+            cr.set_source_rgb(0.9, 0.9, 0.9);
+            for &(x, _) in &x_ticks {
+                let (sx, _) = view.to_screen(x, 0.0, width, height);
+                cr.move_to(sx.round() + 0.5, 0.0);
+                cr.line_to(sx.round() + 0.5, height);
+            }
+            for &(y, _) in &y_ticks {
+                let (_, sy) = view.to_screen(0.0, y, width, height);
+                cr.move_to(0.0, sy.round() + 0.5);
+                cr.line_to(width, sy.round() + 0.5);
+            }
+            cr.stroke();
+        }
+
+        // Axes
+        cr.set_source_rgb(0.3, 0.3, 0.3);
+        cr.move_to(0.0, axis_y.round() + 0.5);
+        cr.line_to(width, axis_y.round() + 0.5);
+        cr.move_to(axis_x.round() + 0.5, 0.0);
+        cr.line_to(axis_x.round() + 0.5, height);
+        cr.stroke();
+
+        // Axis labels
+        for (x, label) in &x_ticks {
+            let (sx, _) = view.to_screen(*x, 0.0, width, height);
+            cr.move_to(sx + 3.0, (axis_y + 14.0).min(height - 3.0));
+            cr.show_text(label);
+        }
+        for (y, label) in &y_ticks {
+            let (_, sy) = view.to_screen(0.0, *y, width, height);
+            cr.move_to((axis_x + 3.0).min(width - 40.0), sy - 3.0);
+            cr.show_text(label);
+        }
+        cr.move_to(width - 12.0, axis_y - 6.0);
+        cr.show_text("x");
+        cr.move_to(axis_x + 6.0, 12.0);
+        cr.show_text("y");
+
+        // Curves
+        let curves = self.sample(&view, width as usize)?;
+        cr.set_line_width(2.0);
+        for (i, curve) in curves.iter().enumerate() {
+            let (r, g, b) = COLORS[i % COLORS.len()];
+            cr.set_source_rgb(r, g, b);
+            let mut last: Option<f64> = None;
+            for &(x, y) in curve {
+                if !y.is_finite() {
+                    last = None;
+                    continue;
+                }
+                let (sx, sy) = view.to_screen(x, y, width, height);
+                match last {
+                    // Break the line on jumps such as asymptotes
+                    Some(ly) if (sy - ly).abs() < height * 2.0 => cr.line_to(sx, sy),
+                    _ => cr.move_to(sx, sy),
+                }
+                last = Some(sy);
+            }
+            cr.stroke();
+        }
+
+        Ok(())
+    }
+}
+
+/// Evenly spaced "nice" tick positions with their labels
+fn ticks(min: f64, max: f64, px: f64) -> Vec<(f64, String)> {
+    let raw = (max - min) / (px / 80.0).max(2.0);
+    if !raw.is_finite() || raw <= 0.0 {
+        return Vec::new();
+    }
+    let mag = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * mag)
+        .find(|s| *s >= raw)
+        .unwrap_or(10.0 * mag);
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    ((min / step).ceil() as i64..=(max / step).floor() as i64)
+        .map(|i| i as f64 * step)
+        .filter(|t| t.abs() > step / 2.0)
+        .map(|t| (t, format!("{:.*}", decimals, t)))
+        .collect()
+}
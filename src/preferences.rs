@@ -0,0 +1,170 @@
+use {
+    crate::{currency, get_obj},
+    gio::{prelude::*, SettingsBindFlags, SettingsExt},
+    glib::{clone, translate::ToGlib},
+    gtk::{
+        prelude::*, Builder, CssProvider, Label, SettingsExt as GtkSettingsExt, StyleContext,
+        Window,
+    },
+    std::rc::Rc,
+};
+
+/// Settings key, widget id and widget property of every preference
+const BINDINGS: &[(&str, &str, &str)] = &[
+    ("editor-font", "editor-font-button", "font"),
+    ("result-font", "result-font-button", "font"),
+    ("theme", "theme-combo", "active-id"),
+    ("eval-trigger", "eval-trigger-combo", "active-id"),
+    ("precision-mode", "precision-mode-combo", "active-id"),
+    ("angle-unit", "angle-unit-combo", "active-id"),
+    ("programmer-mode", "programmer-mode-switch", "active"),
+    ("word-size", "word-size-combo", "active-id"),
+    ("result-notation", "result-notation-combo", "active-id"),
+    ("result-precision", "result-precision-spin", "value"),
+    ("result-separators", "result-separators-switch", "active"),
+    ("result-base", "result-base-combo", "active-id"),
+    ("ask-save-on-exit", "ask-save-switch", "active"),
+    ("autosave", "autosave-switch", "active"),
+    ("autosave-interval", "autosave-interval-spin", "value"),
+];
+
+#[derive(Debug)]
+pub struct Preferences {
+    window: Window,
+    rates_label: Label,
+    fonts: CssProvider,
+    settings: gio::Settings,
+    prefer_dark_default: bool,
+}
+
+impl Preferences {
+    pub fn new(b: &Builder, settings: &gio::Settings) -> Rc<Self> {
+        for (key, id, property) in BINDINGS {
+            settings.bind(
+                key,
+                &get_obj!(b, glib::Object, id),
+                property,
+                SettingsBindFlags::DEFAULT,
+            );
+        }
+
+        let fonts = CssProvider::new();
+        StyleContext::add_provider_for_screen(
+            &gdk::Screen::get_default().expect("Error initializing gtk css provider."),
+            &fonts,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+
+        let this = Rc::new(Self {
+            window: get_obj!(b, "preferences-window"),
+            rates_label: get_obj!(b, "currency-rates-label"),
+            fonts,
+            settings: settings.clone(),
+            prefer_dark_default: gtk::Settings::get_default()
+                .map(|s| s.get_property_gtk_application_prefer_dark_theme())
+                .unwrap_or(false),
+        });
+
+        this.window.connect_delete_event(|w, _| w.hide_on_delete());
+
+        this.apply_theme();
+        this.settings
+            .connect_changed(clone!(@strong this => move |_, key| {
+                match key {
+                    "theme" => this.apply_theme(),
+                    _ => {}
+                }
+            }));
+
+        this
+    }
+
+    pub fn show(&self) {
+        self.update_rates();
+        self.window.present();
+    }
+
+    /// Show the date of the currency rates, the rates file may have changed since last time
+    fn update_rates(&self) {
+        let path = currency::path();
+        let (text, tooltip) = match (&path, currency::rates()) {
+            (Some(p), Ok(rates)) => (
+                match &rates.date {
+                    Some(date) => format!("Rates of {}", date),
+                    None => String::from("Rates without a date"),
+                },
+                p.display().to_string(),
+            ),
+            (Some(_), Err(e)) => (String::from("Invalid rates file"), e),
+            (None, result) => (
+                String::from("No rates file"),
+                result.err().unwrap_or_default(),
+            ),
+        };
+        self.rates_label.set_text(&text);
+        self.rates_label.set_tooltip_text(Some(&tooltip));
+    }
+
+    /// Set the editor and result fonts, the main window reports when they can not be set
+    pub fn apply_fonts(&self) -> Result<(), String> {
+        let css = format!(
+            "{}{}",
+            font_css("textview.big", &self.string("editor-font")),
+            font_css("entry.big", &self.string("result-font"))
+        );
+        self.fonts
+            .load_from_data(css.as_bytes())
+            .map_err(|e| format!("Could not apply the fonts: {}", e))
+    }
+
+    fn apply_theme(&self) {
+        if let Some(gtk_settings) = gtk::Settings::get_default() {
+            gtk_settings.set_property_gtk_application_prefer_dark_theme(
+                match self.string("theme").as_str() {
+                    "light" => false,
+                    "dark" => true,
+                    _ => self.prefer_dark_default,
+                },
+            );
+        }
+    }
+
+    fn string(&self, key: &str) -> String {
+        self.settings
+            .get_string(key)
+            .map(|s| s.to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// CSS rule setting the font of `selector` from a Pango font name such as "Monospace Bold 14"
+fn font_css(selector: &str, font: &str) -> String {
+    if font.is_empty() {
+        return String::new();
+    }
+
+    let desc = pango::FontDescription::from_string(font);
+    let mut css = format!("{} {{", selector);
+    if let Some(family) = desc.get_family() {
+        css.push_str(&format!(" font-family: \"{}\";", family));
+    }
+    if desc.get_size() > 0 {
+        css.push_str(&format!(
+            " font-size: {}{};",
+            f64::from(desc.get_size()) / f64::from(pango::SCALE),
+            if desc.get_size_is_absolute() {
+                "px"
+            } else {
+                "pt"
+            }
+        ));
+    }
+    css.push_str(&format!(" font-weight: {};", desc.get_weight().to_glib()));
+    match desc.get_style() {
+        pango::Style::Italic => css.push_str(" font-style: italic;"),
+        pango::Style::Oblique => css.push_str(" font-style: oblique;"),
+        _ => {}
+    }
+    css.push_str(" }\n");
+    css
+}
@@ -0,0 +1,1424 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token<'a> {
+    Number(&'a str),
+    /// Identifiers, including namespaced ones like `math::sin`
+    Ident(&'a str),
+    /// String literals, quotes included
+    Str(&'a str),
+    Space(&'a str),
+    Punct(&'a str),
+}
+
+impl<'a> Token<'a> {
+    pub fn text(&self) -> &'a str {
+        match *self {
+            Token::Number(s)
+            | Token::Ident(s)
+            | Token::Str(s)
+            | Token::Space(s)
+            | Token::Punct(s) => s,
+        }
+    }
+}
+
+/// Split `source` into tokens; concatenating their text gives back `source`
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let token = if let Some(len) = prefixed_int_len(&bytes[i..]) {
+            i += len;
+            Token::Number(&source[start..i])
+        } else if c.is_ascii_digit() {
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i + 1 < bytes.len() && bytes[i] == b'.' && bytes[i + 1].is_ascii_digit() {
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+                let mut j = i + 1;
+                if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+                    j += 1;
+                }
+                if j < bytes.len() && bytes[j].is_ascii_digit() {
+                    i = j;
+                    while i < bytes.len() && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            Token::Number(&source[start..i])
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            loop {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                if source[i..].starts_with("::") {
+                    i += 2;
+                } else {
+                    break;
+                }
+            }
+            Token::Ident(&source[start..i])
+        } else if c == b'"' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+            Token::Str(&source[start..i])
+        } else if c.is_ascii_whitespace() {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            Token::Space(&source[start..i])
+        } else {
+            i += source[i..].chars().next().map(char::len_utf8).unwrap_or(1);
+            Token::Punct(&source[start..i])
+        };
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Length of the `0x`, `0o` or `0b` prefixed integer literal at the start of `bytes`, if any
+fn prefixed_int_len(bytes: &[u8]) -> Option<usize> {
+    let radix = match (bytes.get(0), bytes.get(1).map(u8::to_ascii_lowercase)) {
+        (Some(b'0'), Some(b'x')) => 16,
+        (Some(b'0'), Some(b'o')) => 8,
+        (Some(b'0'), Some(b'b')) => 2,
+        _ => return None,
+    };
+    match bytes.get(2) {
+        Some(&b) if (b as char).is_digit(radix) => Some(
+            2 + bytes[2..]
+                .iter()
+                .take_while(|&&b| (b as char).is_digit(radix) || b == b'_')
+                .count(),
+        ),
+        _ => None,
+    }
+}
+
+/// Value of a `0x`, `0o` or `0b` prefixed integer literal, `_` may separate digits.
+/// Literals above `i64::MAX` wrap around like a two's complement `u64`.
+pub fn prefixed_int(literal: &str) -> Option<i64> {
+    let radix = match literal.get(..2)?.to_ascii_lowercase().as_str() {
+        "0x" => 16,
+        "0o" => 8,
+        "0b" => 2,
+        _ => return None,
+    };
+    u64::from_str_radix(&literal[2..].replace('_', ""), radix)
+        .ok()
+        .map(|v| v as i64)
+}
+
+/// Turn `0x`, `0o` and `0b` prefixed integer literals into decimal ones
+pub fn int_literals(source: &str) -> String {
+    tokenize(source)
+        .iter()
+        .map(|t| match t {
+            Token::Number(n) => match prefixed_int(n) {
+                Some(v) if v < 0 => format!("({})", v),
+                Some(v) => v.to_string(),
+                None => n.to_string(),
+            },
+            t => t.text().to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod int_literal_tests {
+    use super::*;
+
+    #[test]
+    fn prefixed_int_literals() {
+        assert_eq!(int_literals("0xFF + 0b1010"), "255 + 10");
+        assert_eq!(int_literals("0o17"), "15");
+        assert_eq!(int_literals("0x1_000"), "4096");
+        assert_eq!(int_literals("0xFFFFFFFFFFFFFFFF"), "(-1)");
+        assert_eq!(int_literals("0x"), "0x");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BitOp {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BitOp {
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::Xor => 2,
+            Self::And => 3,
+            Self::Shl | Self::Shr => 4,
+            Self::Add | Self::Sub => 5,
+            Self::Mul | Self::Div | Self::Rem => 6,
+        }
+    }
+
+    fn function(self) -> &'static str {
+        match self {
+            Self::Or => "bit::or",
+            Self::Xor => "bit::xor",
+            Self::And => "bit::and",
+            Self::Shl => "bit::shl",
+            Self::Shr => "bit::shr",
+            Self::Add => "bit::add",
+            Self::Sub => "bit::sub",
+            Self::Mul => "bit::mul",
+            Self::Div => "bit::div",
+            Self::Rem => "bit::rem",
+        }
+    }
+
+    fn arithmetic(op: &str) -> Option<Self> {
+        match op {
+            "+" => Some(Self::Add),
+            "-" => Some(Self::Sub),
+            "*" => Some(Self::Mul),
+            "/" => Some(Self::Div),
+            "%" => Some(Self::Rem),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Op(BitOp),
+    /// Operators binding looser than the bitwise ones, and separators
+    Barrier(String),
+}
+
+/// Rewrite the bitwise operators `&`, `|`, `xor`, `<<`, `>>` and `~` into calls to the `bit::`
+/// functions. They bind tighter than comparisons and looser than arithmetic, like in Python.
+/// With `arithmetic`, as when results wrap around to a word size, `+`, `-`, `*`, `/` and `%`
+/// are rewritten too, so every step of `(200 + 100) / 2` wraps.
+pub fn bitwise_operators(source: &str, arithmetic: bool) -> String {
+    rewrite_bitwise(&tokenize(source), arithmetic)
+}
+
+/// Whether the pieces so far end with an operand, making a `-` after them binary
+fn follows_operand(pieces: &[Piece]) -> bool {
+    let last = pieces
+        .iter()
+        .rev()
+        .find(|p| !matches!(p, Piece::Text(t) if t.trim().is_empty()));
+    match last {
+        Some(Piece::Text(t)) => !t.trim_end().ends_with(&['-', '+', '!', '^'][..]),
+        _ => false,
+    }
+}
+
+fn rewrite_bitwise(tokens: &[Token], arithmetic: bool) -> String {
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let next = tokens.get(i + 1).map(Token::text);
+        let op = match tokens[i] {
+            Token::Punct(p) if arithmetic && next != Some("=") => BitOp::arithmetic(p),
+            _ => None,
+        };
+        // A `-` or `+` not following an operand is a sign
+        if let Some(op) = op.filter(|_| follows_operand(&pieces)) {
+            pieces.push(Piece::Op(op));
+            i += 1;
+            continue;
+        }
+        let (piece, len) = match (tokens[i], next) {
+            (Token::Punct("<"), Some("<")) => (Piece::Op(BitOp::Shl), 2),
+            (Token::Punct(">"), Some(">")) => (Piece::Op(BitOp::Shr), 2),
+            (Token::Punct(a), Some(b))
+                if ["&&", "||", "<=", ">=", "==", "!="].contains(&&*format!("{}{}", a, b))
+                    || (arithmetic && b == "=" && BitOp::arithmetic(a).is_some()) =>
+            {
+                (Piece::Barrier(format!("{}{}", a, b)), 2)
+            }
+            (Token::Punct("&"), _) => (Piece::Op(BitOp::And), 1),
+            (Token::Punct("|"), _) => (Piece::Op(BitOp::Or), 1),
+            (Token::Ident("xor"), _) => (Piece::Op(BitOp::Xor), 1),
+            (Token::Punct(p), _) if ["=", "<", ">", ",", ";"].contains(&p) => {
+                (Piece::Barrier(p.to_string()), 1)
+            }
+            (Token::Punct("~"), _) | (Token::Punct("("), _) => {
+                let (text, len) = operand(&tokens[i..], arithmetic);
+                (Piece::Text(text), len)
+            }
+            (t, _) => (Piece::Text(t.text().to_string()), 1),
+        };
+        pieces.push(piece);
+        i += len;
+    }
+
+    let mut out = String::new();
+    let mut segment = Vec::new();
+    for piece in pieces {
+        match piece {
+            Piece::Barrier(b) => {
+                out.push_str(&combine(&segment));
+                out.push_str(&b);
+                segment.clear();
+            }
+            piece => segment.push(piece),
+        }
+    }
+    out.push_str(&combine(&segment));
+    out
+}
+
+/// The operand starting at `tokens`, with the number of tokens it spans: `~` applied to an
+/// operand, a parenthesised group, or a name followed by call arguments
+fn operand(tokens: &[Token], arithmetic: bool) -> (String, usize) {
+    match tokens.first() {
+        Some(Token::Punct("~")) => {
+            let spaces = tokens[1..]
+                .iter()
+                .take_while(|t| matches!(t, Token::Space(_)))
+                .count();
+            let (inner, len) = operand(&tokens[1 + spaces..], arithmetic);
+            (format!("bit::not({})", inner), 1 + spaces + len)
+        }
+        Some(Token::Punct("(")) => match closing_paren(tokens) {
+            Some(close) => (
+                format!("({})", rewrite_bitwise(&tokens[1..close], arithmetic)),
+                close + 1,
+            ),
+            None => (
+                format!("({}", rewrite_bitwise(&tokens[1..], arithmetic)),
+                tokens.len(),
+            ),
+        },
+        Some(t @ Token::Ident(_)) if tokens.get(1) == Some(&Token::Punct("(")) => {
+            let (args, len) = operand(&tokens[1..], arithmetic);
+            (format!("{}{}", t.text(), args), 1 + len)
+        }
+        Some(t) => (t.text().to_string(), 1),
+        None => (String::new(), 0),
+    }
+}
+
+/// Join a run of text and bitwise operators into nested `bit::` calls, by precedence
+fn combine(segment: &[Piece]) -> String {
+    let mut operands = vec![String::new()];
+    let mut ops = Vec::new();
+    for piece in segment {
+        match piece {
+            Piece::Text(t) | Piece::Barrier(t) => operands.last_mut().unwrap().push_str(t),
+            Piece::Op(op) => {
+                ops.push(*op);
+                operands.push(String::new());
+            }
+        }
+    }
+    if ops.is_empty() {
+        return operands.remove(0);
+    }
+
+    // Whitespace around the whole run stays outside the calls
+    let first = &operands[0];
+    let leading = first[..first.len() - first.trim_start().len()].to_string();
+    let last = operands.last().unwrap();
+    let trailing = last[last.trim_end().len()..].to_string();
+
+    let mut values: Vec<String> = Vec::new();
+    let mut stack: Vec<BitOp> = Vec::new();
+    let reduce = |values: &mut Vec<String>, op: BitOp| {
+        let b = values.pop().unwrap_or_default();
+        let a = values.pop().unwrap_or_default();
+        values.push(format!("{}({}, {})", op.function(), a, b));
+    };
+    let mut operands = operands.into_iter().map(|o| o.trim().to_string());
+    values.push(operands.next().unwrap());
+    for (op, operand) in ops.into_iter().zip(operands) {
+        while let Some(&top) = stack.last() {
+            if top.precedence() < op.precedence() {
+                break;
+            }
+            stack.pop();
+            reduce(&mut values, top);
+        }
+        stack.push(op);
+        values.push(operand);
+    }
+    while let Some(top) = stack.pop() {
+        reduce(&mut values, top);
+    }
+
+    format!(
+        "{}{}{}",
+        leading,
+        values.pop().unwrap_or_default(),
+        trailing
+    )
+}
+
+#[cfg(test)]
+mod bitwise_tests {
+    use super::*;
+
+    #[test]
+    fn bitwise_operators_bind_looser_than_arithmetic() {
+        assert_eq!(
+            bitwise_operators("1 | 2 & 3", false),
+            "bit::or(1, bit::and(2, 3))"
+        );
+        assert_eq!(bitwise_operators("x << 2 + 1", false), "bit::shl(x, 2 + 1)");
+        assert_eq!(
+            bitwise_operators("~a xor b", false),
+            "bit::xor(bit::not(a), b)"
+        );
+        assert_eq!(
+            bitwise_operators("a & b == c", false),
+            "bit::and(a, b) == c"
+        );
+        assert_eq!(bitwise_operators("a && b || c", false), "a && b || c");
+    }
+
+    #[test]
+    fn arithmetic_is_rewritten_when_it_wraps() {
+        assert_eq!(
+            bitwise_operators("(200 + 100) / 2", true),
+            "bit::div((bit::add(200, 100)), 2)"
+        );
+        assert_eq!(
+            bitwise_operators("1 - 2 - 3 * 4", true),
+            "bit::sub(bit::sub(1, 2), bit::mul(3, 4))"
+        );
+        assert_eq!(bitwise_operators("-x + 1", true), "bit::add(-x, 1)");
+        assert_eq!(bitwise_operators("3 * -2", true), "bit::mul(3, -2)");
+        assert_eq!(bitwise_operators("a -= 1 % 2", true), "a -= bit::rem(1, 2)");
+        assert_eq!(
+            bitwise_operators("f(1 + 2) << 1", true),
+            "bit::shl(f(bit::add(1, 2)), 1)"
+        );
+        assert_eq!(
+            bitwise_operators("(200 + 100) / 2", false),
+            "(200 + 100) / 2"
+        );
+    }
+}
+
+/// Seconds in each unit a number can be followed by to make a duration
+const DURATION_UNITS: &[(&str, i64)] = &[
+    ("second", 1),
+    ("seconds", 1),
+    ("minute", 60),
+    ("minutes", 60),
+    ("hour", 3_600),
+    ("hours", 3_600),
+    ("day", 86_400),
+    ("days", 86_400),
+    ("week", 604_800),
+    ("weeks", 604_800),
+];
+
+/// Units of calendar time, which `+` and `-` add with the `date::` function taking them, with
+/// how many of its argument one of them is
+const CALENDAR_UNITS: &[(&str, &str, i64)] = &[
+    ("day", "date::add_days", 1),
+    ("days", "date::add_days", 1),
+    ("week", "date::add_days", 7),
+    ("weeks", "date::add_days", 7),
+    ("month", "date::add_months", 1),
+    ("months", "date::add_months", 1),
+];
+
+/// Turn durations like `30 minutes` into seconds, `date::span(30 * 60)`. Whole days, weeks
+/// and months added or subtracted are calendar ones, so `d + 30 days` is
+/// `date::add_days(d, 30)` and keeps the time of day across daylight saving changes. Like
+/// percentages, they apply to everything before them up to the enclosing parenthesis,
+/// separator, assignment or comparison.
+pub fn duration_units(source: &str) -> String {
+    rewrite_durations(&tokenize(source))
+}
+
+fn rewrite_durations(tokens: &[Token]) -> String {
+    // Tokens and parenthesized groups, and where the current expression starts among them
+    let mut out: Vec<String> = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i] {
+            Token::Punct("(") => {
+                let (text, len) = group(&tokens[i..], rewrite_durations);
+                out.push(text);
+                i += len;
+                continue;
+            }
+            Token::Number(n) => {
+                // The unit is the next token that is not a space
+                let next = i + 1 + spaces(&tokens[i + 1..]);
+                let after = next + 1 + tokens.get(next + 1..).map_or(0, spaces);
+                let unit = match tokens.get(next) {
+                    Some(Token::Ident(u)) => *u,
+                    _ => "",
+                };
+                // `x + n days` and `x - n days`, unless the sign is a unary one or `n days` is
+                // scaled
+                let sign = last_piece(&out, start).filter(|&s| {
+                    (out[s] == "+" || out[s] == "-")
+                        && last_piece(&out[..s], start).map_or(false, |x| is_operand(&out[x]))
+                });
+                let scaled = matches!(
+                    tokens.get(after),
+                    Some(Token::Punct(p)) if ["*", "/", "%", "^"].contains(p)
+                );
+                let calendar = CALENDAR_UNITS
+                    .iter()
+                    .find(|(name, _, _)| *name == unit)
+                    .filter(|_| n.bytes().all(|b| b.is_ascii_digit()) && !scaled);
+                let seconds = DURATION_UNITS.iter().find(|(name, _)| *name == unit);
+                match (calendar, sign, seconds) {
+                    (Some((_, function, size)), Some(s), _) => {
+                        let sign = if out[s] == "-" { "-" } else { "" };
+                        out.truncate(s);
+                        let expr = out.split_off(start).concat();
+                        let leading = &expr[..expr.len() - expr.trim_start().len()];
+                        let count = match size {
+                            1 => format!("{}{}", sign, n),
+                            size => format!("{}{} * {}", sign, n, size),
+                        };
+                        out.push(format!(
+                            "{}{}({}, {})",
+                            leading,
+                            function,
+                            expr.trim(),
+                            count
+                        ));
+                    }
+                    (_, _, Some((_, seconds))) => {
+                        out.push(format!("date::span({} * {})", n, seconds))
+                    }
+                    _ => {
+                        out.push(n.to_string());
+                        i += 1;
+                        continue;
+                    }
+                }
+                i = next + 1;
+                continue;
+            }
+            Token::Punct(p) if [",", ";", "=", "<", ">", "!", "&", "|"].contains(&p) => {
+                out.push(p.to_string());
+                start = out.len();
+            }
+            t => out.push(t.text().to_string()),
+        }
+        i += 1;
+    }
+    out.concat()
+}
+
+#[cfg(test)]
+mod duration_tests {
+    use super::*;
+
+    #[test]
+    fn durations_become_seconds() {
+        assert_eq!(duration_units("2 days"), "date::span(2 * 86400)");
+        assert_eq!(duration_units("1 week"), "date::span(1 * 604800)");
+        assert_eq!(duration_units("d + 3"), "d + 3");
+        assert_eq!(duration_units("d + 2 hours"), "d + date::span(2 * 3600)");
+        assert_eq!(duration_units("-1 day"), "-date::span(1 * 86400)");
+    }
+
+    #[test]
+    fn calendar_units_are_added_to_dates() {
+        assert_eq!(duration_units("d + 30 days"), "date::add_days(d, 30)");
+        assert_eq!(duration_units("d - 2 weeks"), "date::add_days(d, -2 * 7)");
+        assert_eq!(
+            duration_units("x = f(d) + 1 month; x"),
+            "x = date::add_months(f(d), 1); x"
+        );
+        assert_eq!(
+            duration_units("d + 1 day + 1 month"),
+            "date::add_months(date::add_days(d, 1), 1)"
+        );
+        assert_eq!(
+            duration_units("(d + 1 day) < e"),
+            "(date::add_days(d, 1)) < e"
+        );
+        // Parts of days and scaled days are lengths of time
+        assert_eq!(
+            duration_units("d + 1.5 days"),
+            "d + date::span(1.5 * 86400)"
+        );
+        assert_eq!(
+            duration_units("d + 2 days * n"),
+            "d + date::span(2 * 86400) * n"
+        );
+    }
+}
+
+/// Suffixes of angle literals and the `angle::` functions converting them to the angle unit
+const ANGLE_UNITS: &[(&str, &str)] = &[
+    ("rad", "angle::rad"),
+    ("deg", "angle::deg"),
+    ("°", "angle::deg"),
+    ("grad", "angle::grad"),
+];
+
+/// Turn angles like `30 deg` or `30°` into calls converting them to the angle unit,
+/// `angle::deg(30)`
+pub fn angle_units(source: &str) -> String {
+    let tokens = tokenize(source);
+    let mut out = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Token::Number(n) = tokens[i] {
+            let spaces = spaces(&tokens[i + 1..]);
+            let unit = match tokens.get(i + 1 + spaces) {
+                Some(Token::Ident(u)) | Some(Token::Punct(u)) => {
+                    ANGLE_UNITS.iter().find(|(suffix, _)| suffix == u)
+                }
+                _ => None,
+            };
+            if let Some((_, function)) = unit {
+                out.push_str(&format!("{}({})", function, n));
+                i += 2 + spaces;
+                continue;
+            }
+        }
+        out.push_str(tokens[i].text());
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod angle_tests {
+    use super::*;
+
+    #[test]
+    fn angles_are_converted_to_the_angle_unit() {
+        assert_eq!(angle_units("sin(90 deg)"), "sin(angle::deg(90))");
+        assert_eq!(angle_units("30°"), "angle::deg(30)");
+        assert_eq!(angle_units("50 grad"), "angle::grad(50)");
+        assert_eq!(angle_units("x deg"), "x deg");
+    }
+}
+
+/// Rewrite amounts like `100 USD` into `cur::from(100, "USD")`, and conversions like `x in EUR`
+/// or `x to EUR` into `cur::to(x, "EUR")`, for the codes `is_known` accepts, which are those of
+/// the rates file. A conversion applies to everything before it up to the enclosing
+/// parenthesis, `,`, `;` or assignment.
+pub fn currency_units(source: &str, is_known: &dyn Fn(&str) -> bool) -> String {
+    let tokens = tokenize(source);
+    let mut out: Vec<String> = Vec::new();
+    // Where the current expression starts in `out`, for each level of parentheses
+    let mut starts = vec![0];
+    let mut i = 0;
+    while i < tokens.len() {
+        let spaces = tokens[i + 1..]
+            .iter()
+            .take_while(|t| matches!(t, Token::Space(_)))
+            .count();
+        let code = match tokens.get(i + 1 + spaces) {
+            Some(Token::Ident(c)) if is_known(c) => Some(*c),
+            _ => None,
+        };
+        match (tokens[i], code) {
+            (Token::Number(n), Some(code)) => {
+                out.push(format!("cur::from({}, \"{}\")", n, code));
+                i += 2 + spaces;
+                continue;
+            }
+            (Token::Ident("in"), Some(code)) | (Token::Ident("to"), Some(code)) => {
+                let expr = out.split_off(*starts.last().unwrap()).concat();
+                let leading = &expr[..expr.len() - expr.trim_start().len()];
+                out.push(format!("{}cur::to({}, \"{}\")", leading, expr.trim(), code));
+                i += 2 + spaces;
+                continue;
+            }
+            (Token::Punct("("), _) => {
+                out.push(String::from("("));
+                starts.push(out.len());
+            }
+            (Token::Punct(")"), _) => {
+                if starts.len() > 1 {
+                    starts.pop();
+                }
+                out.push(String::from(")"));
+            }
+            (Token::Punct(p), _) if [",", ";", "="].contains(&p) => {
+                out.push(p.to_string());
+                *starts.last_mut().unwrap() = out.len();
+            }
+            (t, _) => out.push(t.text().to_string()),
+        }
+        i += 1;
+    }
+    out.concat()
+}
+
+#[cfg(test)]
+mod currency_tests {
+    use super::*;
+
+    #[test]
+    fn amounts_and_conversions() {
+        let known = |code: &str| code == "USD" || code == "EUR";
+        assert_eq!(
+            currency_units("10 EUR + 5 EUR", &known),
+            "cur::from(10, \"EUR\") + cur::from(5, \"EUR\")"
+        );
+        assert_eq!(
+            currency_units("x = 10 USD in EUR", &known),
+            "x = cur::to(cur::from(10, \"USD\"), \"EUR\")"
+        );
+        // Only codes of the rates file are currencies
+        assert_eq!(currency_units("5 XYZ", &known), "5 XYZ");
+    }
+}
+
+/// Number of spaces `tokens` starts with
+fn spaces(tokens: &[Token]) -> usize {
+    tokens
+        .iter()
+        .take_while(|t| matches!(t, Token::Space(_)))
+        .count()
+}
+
+/// Index of the last piece of `pieces` from `start` on that is not a space
+fn last_piece(pieces: &[String], start: usize) -> Option<usize> {
+    (start..pieces.len())
+        .rev()
+        .find(|&p| !pieces[p].trim().is_empty())
+}
+
+/// Whether `piece`, a token or a parenthesized group, is an operand
+fn is_operand(piece: &str) -> bool {
+    piece
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_alphanumeric() || "_(\"".contains(c))
+}
+
+/// Whether the `%` at `i` is a percent sign rather than the modulo operator, that is whether
+/// no operand follows it. A sign followed by a space is an operator, `15% - 5`, and one that
+/// is not is part of an operand, `7 % -2`.
+fn is_percent_sign(tokens: &[Token], i: usize) -> bool {
+    if tokens.get(i + 1) == Some(&Token::Punct("=")) {
+        return false;
+    }
+    let next = &tokens[i + 1 + spaces(&tokens[i + 1..])..];
+    match next.first() {
+        None | Some(Token::Ident("of")) => true,
+        Some(Token::Number(_)) | Some(Token::Ident(_)) | Some(Token::Str(_)) => false,
+        Some(Token::Punct("(")) | Some(Token::Punct("~")) => false,
+        Some(Token::Punct("-")) | Some(Token::Punct("+")) => {
+            matches!(next.get(1), Some(Token::Space(_)))
+        }
+        Some(Token::Punct("!")) => next.get(1) == Some(&Token::Punct("=")),
+        Some(_) => true,
+    }
+}
+
+/// The operand `of` applies to at the start of `tokens`, with the number of tokens it spans:
+/// a signed number or name, a call, or a parenthesized group
+fn percent_operand(tokens: &[Token]) -> (String, usize) {
+    let spaces = spaces(tokens);
+    let (sign, start) = match tokens.get(spaces) {
+        Some(Token::Punct("-")) => ("-", spaces + 1),
+        _ => ("", spaces),
+    };
+    let (text, len) = match (tokens.get(start), tokens.get(start + 1)) {
+        (Some(Token::Punct("(")), _) => group(&tokens[start..], rewrite_percentages),
+        (Some(t @ Token::Ident(_)), Some(Token::Punct("("))) => {
+            let (args, len) = group(&tokens[start + 1..], rewrite_percentages);
+            (format!("{}{}", t.text(), args), len + 1)
+        }
+        (Some(t), _) => (t.text().to_string(), 1),
+        (None, _) => (String::new(), 0),
+    };
+    (format!("{}{}", sign, text), start + len)
+}
+
+/// The parenthesized group `tokens` starts with, with `rewrite` applied to what is inside, and
+/// the number of tokens it spans
+fn group(tokens: &[Token], rewrite: fn(&[Token]) -> String) -> (String, usize) {
+    match closing_paren(tokens) {
+        Some(close) => (format!("({})", rewrite(&tokens[1..close])), close + 1),
+        None => (format!("({}", rewrite(&tokens[1..])), tokens.len()),
+    }
+}
+
+/// Rewrite percentages the way desk calculators read them, into calls to the `pct::`
+/// functions: `15%` is `pct::of(15, 1)`, `20% of 350` is `pct::of(20, 350)`, `200 + 15%` adds
+/// 15% of 200, `pct::add(200, 15)`, and `70 as % of 350` is `pct::ratio(70, 350)`. Like
+/// conversions, `+`, `-` and `as % of` apply to everything before them up to the enclosing
+/// parenthesis, separator, assignment or comparison, and `of` to the operand after it.
+/// A `%` followed by an operand is still the modulo operator.
+pub fn percentages(source: &str) -> String {
+    rewrite_percentages(&tokenize(source))
+}
+
+fn rewrite_percentages(tokens: &[Token]) -> String {
+    // Tokens and parenthesized groups, and where the current expression starts among them
+    let mut out: Vec<String> = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        // The next token that is not a space
+        let next = i + 1 + spaces(&tokens[i + 1..]);
+        match tokens[i] {
+            Token::Punct("(") => {
+                let (text, len) = group(&tokens[i..], rewrite_percentages);
+                out.push(text);
+                i += len;
+                continue;
+            }
+            Token::Ident("as")
+                if tokens.get(next) == Some(&Token::Punct("%"))
+                    && tokens.get(next + 1 + spaces(&tokens[next + 1..]))
+                        == Some(&Token::Ident("of")) =>
+            {
+                let of = next + 1 + spaces(&tokens[next + 1..]);
+                let (of_what, len) = percent_operand(&tokens[of + 1..]);
+                let expr = out.split_off(start).concat();
+                let leading = &expr[..expr.len() - expr.trim_start().len()];
+                out.push(format!(
+                    "{}pct::ratio({}, {})",
+                    leading,
+                    expr.trim(),
+                    of_what
+                ));
+                i = of + 1 + len;
+                continue;
+            }
+            Token::Punct("%") if is_percent_sign(tokens, i) => {
+                let operand = match last_piece(&out, start) {
+                    Some(o) if is_operand(&out[o]) => o,
+                    _ => {
+                        out.push(String::from("%"));
+                        i += 1;
+                        continue;
+                    }
+                };
+                // A group right after a name is the arguments of a call
+                let operand = match operand.checked_sub(1) {
+                    Some(name)
+                        if name >= start
+                            && out[operand].starts_with('(')
+                            && out[name].starts_with(|c: char| c.is_alphabetic() || c == '_') =>
+                    {
+                        name
+                    }
+                    _ => operand,
+                };
+                let percent = out.split_off(operand).concat();
+                let percent = percent.trim_end();
+
+                if tokens.get(next) == Some(&Token::Ident("of")) {
+                    let (of_what, len) = percent_operand(&tokens[next + 1..]);
+                    out.push(format!("pct::of({}, {})", percent, of_what));
+                    i = next + 1 + len;
+                    continue;
+                }
+                // `x + p%` and `x - p%`, unless the sign is a unary one
+                let sign = last_piece(&out, start).filter(|&s| out[s] == "+" || out[s] == "-");
+                match sign {
+                    Some(s)
+                        if last_piece(&out[..s], start).map_or(false, |x| is_operand(&out[x])) =>
+                    {
+                        let function = if out[s] == "+" {
+                            "pct::add"
+                        } else {
+                            "pct::sub"
+                        };
+                        out.truncate(s);
+                        let expr = out.split_off(start).concat();
+                        let leading = &expr[..expr.len() - expr.trim_start().len()];
+                        out.push(format!(
+                            "{}{}({}, {})",
+                            leading,
+                            function,
+                            expr.trim(),
+                            percent
+                        ));
+                    }
+                    _ => out.push(format!("pct::of({}, 1)", percent)),
+                }
+            }
+            Token::Punct(p) if [",", ";", "=", "<", ">", "!", "&", "|"].contains(&p) => {
+                out.push(p.to_string());
+                start = out.len();
+            }
+            t => out.push(t.text().to_string()),
+        }
+        i += 1;
+    }
+    out.concat()
+}
+
+#[cfg(test)]
+mod percent_tests {
+    use super::*;
+
+    #[test]
+    fn percent_rewrites() {
+        assert_eq!(percentages("50%"), "pct::of(50, 1)");
+        assert_eq!(percentages("20% of 150"), "pct::of(20, 150)");
+        assert_eq!(percentages("100 + 10%"), "pct::add(100, 10)");
+        assert_eq!(percentages("100 - 10%"), "pct::sub(100, 10)");
+        assert_eq!(percentages("30 as % of 120"), "pct::ratio(30, 120)");
+        assert_eq!(percentages("x * 10%"), "x * pct::of(10, 1)");
+        // The remainder operator is left alone
+        assert_eq!(percentages("5 % 3"), "5 % 3");
+    }
+}
+
+/// Index of the `)` closing the `(` that `tokens` starts with
+fn closing_paren(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    tokens.iter().position(|t| {
+        match t {
+            Token::Punct("(") => depth += 1,
+            Token::Punct(")") => depth -= 1,
+            _ => {}
+        }
+        depth == 0
+    })
+}
+
+/// Split call arguments at the `,` outside of parentheses
+fn split_args<'a, 'b>(tokens: &'b [Token<'a>]) -> Vec<&'b [Token<'a>]> {
+    let mut args = Vec::new();
+    let (mut start, mut depth) = (0, 0);
+    for (i, t) in tokens.iter().enumerate() {
+        match t {
+            Token::Punct("(") => depth += 1,
+            Token::Punct(")") => depth -= 1,
+            Token::Punct(",") if depth == 0 => {
+                args.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(&tokens[start..]);
+    args
+}
+
+fn text(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(Token::text)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// `text` as a string literal
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Functions working on the text of their arguments rather than on their values
+const SYMBOLIC: &[(&str, &str)] = &[("diff", "sym::diff"), ("simplify", "sym::simplify")];
+
+/// Quote the arguments of symbolic functions, `diff(x^2, x)` becomes `sym::diff("x^2", "x")`
+pub fn symbolic_calls(source: &str) -> String {
+    let tokens = tokenize(source);
+    let mut out = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let function = match (tokens[i], tokens.get(i + 1)) {
+            (Token::Ident(name), Some(Token::Punct("("))) => {
+                SYMBOLIC.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+            }
+            _ => None,
+        };
+        if let (Some(function), Some(close)) = (function, closing_paren(&tokens[i + 1..])) {
+            let close = i + 1 + close;
+            let args = split_args(&tokens[i + 2..close])
+                .into_iter()
+                .map(|arg| quote(&text(arg)))
+                .collect::<Vec<_>>();
+            out.push_str(&format!("{}({})", function, args.join(", ")));
+            i = close + 1;
+            continue;
+        }
+        out.push_str(tokens[i].text());
+        i += 1;
+    }
+    out
+}
+
+/// Where a function evaluating an expression of its own finds the expression and its variables
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layout {
+    /// `f(expression, variables, …)`, like `solve(x^2 == 2, x)`
+    Named,
+    /// `f(expression, …)` over these variables, or the ones of a lambda, `map(n -> n^2, xs)`
+    Implicit(&'static [&'static str]),
+    /// `f(variable, …, expression)`, like `sum_over(i, 1, n, i^2)`
+    Bound,
+}
+
+/// Functions evaluating an expression over variables of their own: the name they are written
+/// with, the builtin they are rewritten into, where their expression and variables are, and
+/// whether the expression is an equation
+const CLOSURES: &[(&str, &str, Layout, bool)] = &[
+    ("solve", "num::solve", Layout::Named, true),
+    ("root", "num::root", Layout::Implicit(&["x"]), false),
+    (
+        "integrate",
+        "num::integrate",
+        Layout::Implicit(&["x"]),
+        false,
+    ),
+    ("quad", "num::quad", Layout::Implicit(&["x"]), false),
+    (
+        "derivative",
+        "num::derivative",
+        Layout::Implicit(&["x"]),
+        false,
+    ),
+    ("limit", "num::limit", Layout::Implicit(&["x"]), false),
+    ("ode", "num::ode", Layout::Implicit(&["t", "y"]), false),
+    ("map", "seq::map", Layout::Implicit(&["x"]), false),
+    ("filter", "seq::filter", Layout::Implicit(&["x"]), false),
+    (
+        "reduce",
+        "seq::reduce",
+        Layout::Implicit(&["acc", "x"]),
+        false,
+    ),
+    ("sum_over", "seq::sum_over", Layout::Bound, false),
+    ("product_over", "seq::product_over", Layout::Bound, false),
+];
+
+/// The name the builtin `function` is written with, `diff` for `sym::diff`, for builtins
+/// that are only called through a rewrite
+pub fn written_name(function: &str) -> Option<&'static str> {
+    SYMBOLIC
+        .iter()
+        .copied()
+        .chain(CLOSURES.iter().map(|&(name, f, ..)| (name, f)))
+        .find(|&(_, f)| f == function)
+        .map(|(name, _)| name)
+}
+
+/// `lhs == rhs` as `(lhs) - (rhs)`, which is 0 where the equation holds. A parenthesized tuple
+/// of equations is rewritten element by element.
+fn residual(tokens: &[Token]) -> String {
+    let tokens = trim_spaces(tokens);
+    if tokens.first() == Some(&Token::Punct("(")) && closing_paren(tokens) == Some(tokens.len() - 1)
+    {
+        let elements = split_args(&tokens[1..tokens.len() - 1]);
+        let elements = elements.into_iter().map(residual).collect::<Vec<_>>();
+        return format!("({})", elements.join(", "));
+    }
+
+    let mut depth = 0;
+    for (i, pair) in tokens.windows(2).enumerate() {
+        match pair[0] {
+            Token::Punct("(") => depth += 1,
+            Token::Punct(")") => depth -= 1,
+            Token::Punct("=") if depth == 0 && pair[1] == Token::Punct("=") => {
+                return format!("({}) - ({})", text(&tokens[..i]), text(&tokens[i + 2..]));
+            }
+            _ => {}
+        }
+    }
+    text(tokens)
+}
+
+fn trim_spaces<'a, 'b>(tokens: &'b [Token<'a>]) -> &'b [Token<'a>] {
+    let start = tokens
+        .iter()
+        .position(|t| !matches!(t, Token::Space(_)))
+        .unwrap_or(tokens.len());
+    let end = tokens
+        .iter()
+        .rposition(|t| !matches!(t, Token::Space(_)))
+        .map_or(start, |i| i + 1);
+    &tokens[start..end]
+}
+
+/// Names of the variables in `x` or `(x, y)`
+fn variable_names(tokens: &[Token]) -> Vec<String> {
+    let tokens = trim_spaces(tokens);
+    match (tokens.first(), closing_paren(tokens)) {
+        (Some(Token::Punct("(")), Some(close)) if close == tokens.len() - 1 => {
+            split_args(&tokens[1..close])
+                .into_iter()
+                .map(text)
+                .collect()
+        }
+        _ => vec![text(tokens)],
+    }
+}
+
+/// Split `x -> body` or `(x, y) -> body` into the names of the variables and the body
+fn lambda<'a, 'b>(tokens: &'b [Token<'a>]) -> Option<(Vec<String>, &'b [Token<'a>])> {
+    let mut depth = 0;
+    for (i, pair) in tokens.windows(2).enumerate() {
+        match pair {
+            [Token::Punct("("), _] => depth += 1,
+            [Token::Punct(")"), _] => depth -= 1,
+            [Token::Punct("-"), Token::Punct(">")] if depth == 0 => {
+                return Some((variable_names(&tokens[..i]), &tokens[i + 2..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The variables `body` uses besides `params`, as `("a", a, "b", b)`, so the builtin evaluating
+/// it sees their values
+fn bindings(body: &str, params: &[String]) -> String {
+    let tokens = tokenize(body);
+    let mut names: Vec<&str> = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let called = trim_spaces(&tokens[i + 1..]).first() == Some(&Token::Punct("("));
+        match t {
+            Token::Ident(name)
+                if !called
+                    && !["true", "false"].contains(name)
+                    && !params.iter().any(|p| p == name)
+                    && !names.contains(name) =>
+            {
+                names.push(name)
+            }
+            _ => {}
+        }
+    }
+    let pairs = names
+        .iter()
+        .map(|name| format!("\"{}\", {}", name, name))
+        .collect::<Vec<_>>();
+    format!("({})", pairs.join(", "))
+}
+
+/// Quote the expressions of functions evaluating them over variables of their own, with the
+/// values of the other variables they use, so `solve(x^2 == a, x, 0, 10)` becomes
+/// `num::solve("(x^2) - (a)", ("x"), ("a", a), 0, 10)`. The quoted expressions are rewritten
+/// with `prepare`, since later rewrites do not look into strings.
+pub fn closure_calls(source: &str, prepare: &dyn Fn(&str) -> String) -> String {
+    let tokens = tokenize(source);
+    let mut out = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let function = match (tokens[i], tokens.get(i + 1)) {
+            (Token::Ident(name), Some(Token::Punct("("))) => {
+                CLOSURES.iter().find(|(n, ..)| *n == name)
+            }
+            _ => None,
+        };
+        if let (Some(&(_, function, layout, equations)), Some(close)) =
+            (function, closing_paren(&tokens[i + 1..]))
+        {
+            let close = i + 1 + close;
+            let args = split_args(&tokens[i + 2..close]);
+            let (params, expr, rest) = match (layout, args.as_slice()) {
+                (Layout::Named, [expr, names, rest @ ..]) => (variable_names(names), *expr, rest),
+                (Layout::Implicit(names), [expr, rest @ ..]) => match lambda(expr) {
+                    Some((params, body)) => (params, body, rest),
+                    None => (names.iter().map(|n| n.to_string()).collect(), *expr, rest),
+                },
+                (Layout::Bound, [names, rest @ .., expr]) => (variable_names(names), *expr, rest),
+                (_, [expr, rest @ ..]) => (Vec::new(), *expr, rest),
+                (_, []) => (Vec::new(), &[][..], &[][..]),
+            };
+
+            let body = prepare(&match equations {
+                true => residual(expr),
+                false => text(expr),
+            });
+            let mut call = vec![
+                quote(&body),
+                format!(
+                    "({})",
+                    params
+                        .iter()
+                        .map(|p| quote(p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                bindings(&body, &params),
+            ];
+            call.extend(rest.iter().map(|arg| closure_calls(&text(arg), prepare)));
+            out.push_str(&format!("{}({})", function, call.join(", ")));
+            i = close + 1;
+            continue;
+        }
+        out.push_str(tokens[i].text());
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod closure_tests {
+    use super::*;
+
+    #[test]
+    fn lambdas_are_quoted_with_their_variables() {
+        let same = |body: &str| body.to_string();
+        assert_eq!(
+            closure_calls("map(x -> x * k, xs)", &same),
+            "seq::map(\"x * k\", (\"x\"), (\"k\", k), xs)"
+        );
+        assert_eq!(
+            closure_calls("reduce((a, b) -> a + b, xs, 0)", &same),
+            "seq::reduce(\"a + b\", (\"a\", \"b\"), (), xs, 0)"
+        );
+        assert_eq!(
+            closure_calls("sum_over(i, 1, 3, i^2)", &same),
+            "seq::sum_over(\"i^2\", (\"i\"), (), 1, 3)"
+        );
+        assert_eq!(
+            closure_calls("solve(x^2 == a, x, 0, 10)", &same),
+            "num::solve(\"(x^2) - (a)\", (\"x\"), (\"a\", a), 0, 10)"
+        );
+        // Bodies are prepared, since later rewrites do not look into strings
+        assert_eq!(
+            closure_calls("map(x -> x + 1, xs)", &|body| body.replace('1', "1.0")),
+            "seq::map(\"x + 1.0\", (\"x\"), (), xs)"
+        );
+    }
+
+    #[test]
+    fn written_names() {
+        assert_eq!(written_name("sym::diff"), Some("diff"));
+        assert_eq!(written_name("num::solve"), Some("solve"));
+        assert_eq!(written_name("seq::sum_over"), Some("sum_over"));
+        assert_eq!(written_name("math::sin"), None);
+    }
+}
+
+/// Index of the `]` closing the `[` that `tokens` starts with
+fn closing_bracket(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0;
+    tokens.iter().position(|t| {
+        match t {
+            Token::Punct("[") => depth += 1,
+            Token::Punct("]") => depth -= 1,
+            _ => {}
+        }
+        depth == 0
+    })
+}
+
+/// Index of the identifier `word` in `tokens`, outside of parentheses and brackets
+fn keyword(tokens: &[Token], word: &str) -> Option<usize> {
+    let mut depth = 0;
+    tokens.iter().position(|t| {
+        match t {
+            Token::Punct("(") | Token::Punct("[") => depth += 1,
+            Token::Punct(")") | Token::Punct("]") => depth -= 1,
+            _ => {}
+        }
+        depth == 0 && *t == Token::Ident(word)
+    })
+}
+
+/// Rewrite list comprehensions into `map` and `filter`, so `[x^2 for x in 1..10 if x % 2 == 0]`
+/// becomes `map(x -> x^2, filter(x -> x % 2 == 0, 1..10))`
+pub fn comprehensions(source: &str) -> String {
+    let tokens = tokenize(source);
+    let mut out = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if tokens[i] == Token::Punct("[") {
+            if let Some(close) = closing_bracket(&tokens[i..]) {
+                let inner = &tokens[i + 1..i + close];
+                if let Some(f) = keyword(inner, "for") {
+                    if let Some(n) = keyword(&inner[f..], "in") {
+                        let (expr, vars) = (&inner[..f], &inner[f + 1..f + n]);
+                        let (list, condition) = match keyword(&inner[f + n..], "if") {
+                            Some(c) => {
+                                (&inner[f + n + 1..f + n + c], Some(&inner[f + n + c + 1..]))
+                            }
+                            None => (&inner[f + n + 1..], None),
+                        };
+                        let (vars, expr) = (text(vars), comprehensions(&text(expr)));
+                        let list = comprehensions(&text(list));
+                        let list = match condition {
+                            Some(c) => {
+                                format!(
+                                    "filter({} -> {}, {})",
+                                    vars,
+                                    comprehensions(&text(c)),
+                                    list
+                                )
+                            }
+                            None => list,
+                        };
+                        out.push_str(&format!("map({} -> {}, {})", vars, expr, list));
+                        i += close + 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        out.push_str(tokens[i].text());
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod comprehension_tests {
+    use super::*;
+
+    #[test]
+    fn comprehensions_become_map_and_filter() {
+        assert_eq!(comprehensions("[x * 2 for x in xs]"), "map(x -> x * 2, xs)");
+        assert_eq!(
+            comprehensions("[x for x in 1..5 if x % 2 == 0]"),
+            "map(x -> x, filter(x -> x % 2 == 0, 1..5))"
+        );
+    }
+}
+
+/// Rewrite ranges into `range` calls, so `1..10` becomes `range(1, 10)` and `0..1 step 0.1`
+/// becomes `range(0, 1, 0.1)`. Ranges take the arithmetic around `..`, so `1..n + 1` is
+/// `range(1, n + 1)` and `x == 1..3` is `x == range(1, 3)`.
+pub fn ranges(source: &str) -> String {
+    rewrite_ranges(&tokenize(source))
+}
+
+/// Whether the punctuation at `i` is repeated right before or after it, like the `&` of `&&` or
+/// the `<` of a shift
+fn doubled(tokens: &[Token], i: usize) -> bool {
+    (i > 0 && tokens[i - 1] == tokens[i]) || tokens.get(i + 1) == Some(&tokens[i])
+}
+
+fn rewrite_ranges(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    // The current segment, with the positions of its `..` and `step`
+    let mut segment: Vec<String> = Vec::new();
+    let (mut dots, mut step) = (None, None);
+    let mut i = 0;
+    let flush =
+        |out: &mut String, segment: &mut Vec<String>, dots: Option<usize>, step: Option<usize>| {
+            let text = segment.concat();
+            match dots {
+                Some(d) => {
+                    let to = step.unwrap_or(segment.len());
+                    let mut args = vec![segment[..d].concat(), segment[d + 1..to].concat()];
+                    if let Some(s) = step {
+                        args.push(segment[s + 1..].concat());
+                    }
+                    // Keep the spaces around the range
+                    let lead = &text[..text.len() - text.trim_start().len()];
+                    let trail = &text[text.trim_end().len()..];
+                    let args = args.iter().map(|a| a.trim()).collect::<Vec<_>>();
+                    out.push_str(&format!("{}range({}){}", lead, args.join(", "), trail));
+                }
+                None => out.push_str(&text),
+            }
+            segment.clear();
+        };
+
+    while i < tokens.len() {
+        match tokens[i] {
+            Token::Punct("(") => {
+                let close = closing_paren(&tokens[i..]).map_or(tokens.len(), |c| i + c);
+                let inner = rewrite_ranges(&tokens[i + 1..close.min(tokens.len())]);
+                let closing = if close < tokens.len() { ")" } else { "" };
+                segment.push(format!("({}{}", inner, closing));
+                i = close + 1;
+                continue;
+            }
+            // Ranges bind looser than arithmetic and bitwise operators, and tighter than
+            // comparisons, logical operators and assignments
+            Token::Punct(p)
+                if [",", ";", "=", "!"].contains(&p)
+                    || (["<", ">"].contains(&p) && !doubled(tokens, i))
+                    || (["&", "|"].contains(&p) && doubled(tokens, i)) =>
+            {
+                flush(&mut out, &mut segment, dots.take(), step.take());
+                out.push_str(p);
+            }
+            Token::Punct(".")
+                if tokens.get(i + 1) == Some(&Token::Punct(".")) && dots.is_none() =>
+            {
+                dots = Some(segment.len());
+                segment.push(String::from(".."));
+                i += 2;
+                continue;
+            }
+            Token::Ident("step")
+                if step.is_none()
+                    && dots.map_or(false, |d| {
+                        segment[d + 1..].iter().any(|s| !s.trim().is_empty())
+                    }) =>
+            {
+                step = Some(segment.len());
+                segment.push(String::from("step"));
+            }
+            t => segment.push(t.text().to_string()),
+        }
+        i += 1;
+    }
+    flush(&mut out, &mut segment, dots, step);
+    out
+}
+
+#[cfg(test)]
+mod range_tests {
+    use super::*;
+
+    #[test]
+    fn ranges_take_the_arithmetic_around_them() {
+        assert_eq!(ranges("sum(1..10)"), "sum(range(1, 10))");
+        assert_eq!(ranges("1..n + 1"), "range(1, n + 1)");
+        assert_eq!(ranges("0..1 step 0.1"), "range(0, 1, 0.1)");
+        assert_eq!(ranges("x == 1..3"), "x == range(1, 3)");
+        assert_eq!(ranges("(1..3, 4..6)"), "(range(1, 3), range(4, 6))");
+        assert_eq!(ranges("a = 1..3; b"), "a = range(1, 3); b");
+    }
+}
+
+/// Turn integer literals into float literals so all arithmetic is done in floating point
+pub fn float_literals(source: &str) -> String {
+    tokenize(source)
+        .iter()
+        .map(|t| match t {
+            Token::Number(n) if !n.contains(|c| c == '.' || c == 'e' || c == 'E') => {
+                format!("{}.0", n)
+            }
+            t => t.text().to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod float_literal_tests {
+    use super::*;
+
+    #[test]
+    fn integers_become_floats() {
+        assert_eq!(float_literals("7 / 2"), "7.0 / 2.0");
+        assert_eq!(float_literals("1.5 + 2"), "1.5 + 2.0");
+        assert_eq!(float_literals("1e3 + x2"), "1e3 + x2");
+    }
+}
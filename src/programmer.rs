@@ -0,0 +1,305 @@
+use evalexpr::{
+    ContextWithMutableFunctions, EvalexprError, EvalexprResult, Function, HashMapContext, Value,
+};
+
+/// Fixed size integer results wrap around to, like a machine word
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Word {
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl Default for Word {
+    fn default() -> Self {
+        Self {
+            bits: 64,
+            signed: true,
+        }
+    }
+}
+
+impl Word {
+    /// `u8`, `u16`, `u32`, `u64`, `i8`, `i16`, `i32` or `i64`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let signed = match name.get(..1)? {
+            "u" => false,
+            "i" => true,
+            _ => return None,
+        };
+        match name[1..].parse() {
+            Ok(bits @ 8) | Ok(bits @ 16) | Ok(bits @ 32) | Ok(bits @ 64) => {
+                Some(Self { bits, signed })
+            }
+            _ => None,
+        }
+    }
+
+    /// The bits of `value` that fit in this word
+    fn bits_of(self, value: i64) -> u64 {
+        match self.bits {
+            64 => value as u64,
+            bits => value as u64 & ((1 << bits) - 1),
+        }
+    }
+
+    /// Wrap `value` into this word using two's complement. `u64` values above `i64::MAX` stay
+    /// negative, `format` shows them unsigned.
+    pub fn wrap(self, value: i64) -> i64 {
+        let bits = self.bits_of(value);
+        if self.signed && self.bits < 64 && bits >> (self.bits - 1) & 1 == 1 {
+            bits as i64 - (1 << self.bits)
+        } else {
+            bits as i64
+        }
+    }
+}
+
+/// `value` in decimal, hexadecimal and binary, e.g. `10 = 0x0A = 0b0000_1010` for a `u8`
+pub fn format(value: i64, word: Word) -> String {
+    let value = word.wrap(value);
+    let bits = word.bits_of(value);
+    let decimal = match word.signed {
+        true => value.to_string(),
+        false => bits.to_string(),
+    };
+    let binary = format!("{:0w$b}", bits, w = word.bits as usize);
+    let binary = binary
+        .as_bytes()
+        .chunks(4)
+        .map(|c| std::str::from_utf8(c).unwrap())
+        .collect::<Vec<_>>()
+        .join("_");
+    format!(
+        "{} = 0x{:0w$X} = 0b{}",
+        decimal,
+        bits,
+        binary,
+        w = word.bits as usize / 4
+    )
+}
+
+/// Integer operand of a bitwise operator, floats are accepted when they hold an integer
+fn integer(value: &Value) -> EvalexprResult<i64> {
+    match value {
+        Value::Int(i) => Ok(*i),
+        Value::Float(f) if f.fract() == 0.0 && f.abs() < 2f64.powi(63) => Ok(*f as i64),
+        v => Err(EvalexprError::CustomMessage(format!(
+            "Bitwise operators need integers, got {}",
+            v
+        ))),
+    }
+}
+
+fn pair(value: &Value) -> EvalexprResult<(i64, i64)> {
+    match value.as_tuple()?.as_slice() {
+        [a, b] => Ok((integer(a)?, integer(b)?)),
+        args => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 2,
+            actual: args.len(),
+        }),
+    }
+}
+
+fn shift(value: i64, amount: i64, left: bool, word: Option<Word>) -> i64 {
+    let amount = amount.max(0).min(u32::MAX as i64) as u32;
+    match word {
+        // Unsigned words shift in zeros from the left
+        Some(w) if !w.signed => {
+            let bits = w.bits_of(value);
+            match left {
+                true => bits.checked_shl(amount),
+                false => bits.checked_shr(amount),
+            }
+            .unwrap_or(0) as i64
+        }
+        _ => match left {
+            true => value.checked_shl(amount).unwrap_or(0),
+            false => value
+                .checked_shr(amount)
+                .unwrap_or(if value < 0 { -1 } else { 0 }),
+        },
+    }
+}
+
+const BINARY: &[(&str, fn(i64, i64) -> i64)] = &[
+    ("bit::and", |a, b| a & b),
+    ("bit::or", |a, b| a | b),
+    ("bit::xor", |a, b| a ^ b),
+];
+
+/// An arithmetic operator: the `bit::` function it is rewritten into, and what it does to
+/// integers, to unsigned integers where that differs, and to floats
+type Arithmetic = (
+    &'static str,
+    fn(i64, i64) -> i64,
+    Option<fn(u64, u64) -> u64>,
+    fn(f64, f64) -> f64,
+);
+
+/// The operators `+`, `-`, `*`, `/` and `%`, which are rewritten into `bit::` functions when
+/// results wrap around to a word size
+const ARITHMETIC: &[Arithmetic] = &[
+    ("bit::add", i64::wrapping_add, None, |a, b| a + b),
+    ("bit::sub", i64::wrapping_sub, None, |a, b| a - b),
+    ("bit::mul", i64::wrapping_mul, None, |a, b| a * b),
+    (
+        "bit::div",
+        i64::wrapping_div,
+        Some(u64::wrapping_div),
+        |a, b| a / b,
+    ),
+    (
+        "bit::rem",
+        i64::wrapping_rem,
+        Some(u64::wrapping_rem),
+        |a, b| a % b,
+    ),
+];
+
+/// `a op b` with integer results wrapped to `word`, floats are left as they are
+fn arithmetic(
+    (name, signed, unsigned, float): Arithmetic,
+    a: &Value,
+    b: &Value,
+    word: Option<Word>,
+) -> EvalexprResult<Value> {
+    match (a, b) {
+        (&Value::Int(a), &Value::Int(b)) => {
+            let (a, b) = match word {
+                Some(w) => (w.wrap(a), w.wrap(b)),
+                None => (a, b),
+            };
+            if b == 0 && unsigned.is_some() {
+                return Err(EvalexprError::CustomMessage(String::from(
+                    "Division by zero",
+                )));
+            }
+            Ok(Value::Int(match (word, unsigned) {
+                (Some(w), Some(unsigned)) if !w.signed => {
+                    w.wrap(unsigned(w.bits_of(a), w.bits_of(b)) as i64)
+                }
+                (Some(w), _) => w.wrap(signed(a, b)),
+                (None, _) => signed(a, b),
+            }))
+        }
+        (Value::String(a), Value::String(b)) if name == "bit::add" => {
+            Ok(Value::String(format!("{}{}", a, b)))
+        }
+        (a, b) => Ok(Value::Float(float(a.as_number()?, b.as_number()?))),
+    }
+}
+
+/// Register the `bit::` functions the bitwise operators are rewritten into. With a `word`,
+/// as in programmer mode, their results wrap around to its size.
+pub fn register(context: &mut HashMapContext, word: Option<Word>) {
+    let wrap = move |v: i64| Value::Int(word.map(|w| w.wrap(v)).unwrap_or(v));
+
+    for &(name, f) in BINARY {
+        context
+            .set_function(
+                name.into(),
+                Function::new(Box::new(move |v| {
+                    let (a, b) = pair(v)?;
+                    Ok(wrap(f(a, b)))
+                })),
+            )
+            .unwrap();
+    }
+    for &(name, left) in &[("bit::shl", true), ("bit::shr", false)] {
+        context
+            .set_function(
+                name.into(),
+                Function::new(Box::new(move |v| {
+                    let (a, b) = pair(v)?;
+                    Ok(wrap(shift(a, b, left, word)))
+                })),
+            )
+            .unwrap();
+    }
+    for &op in ARITHMETIC {
+        context
+            .set_function(
+                op.0.into(),
+                Function::new(Box::new(move |v| match v.as_tuple()?.as_slice() {
+                    [a, b] => arithmetic(op, a, b, word),
+                    args => Err(EvalexprError::WrongFunctionArgumentAmount {
+                        expected: 2,
+                        actual: args.len(),
+                    }),
+                })),
+            )
+            .unwrap();
+    }
+    context
+        .set_function(
+            "bit::not".into(),
+            Function::new(Box::new(move |v| Ok(wrap(!integer(v)?)))),
+        )
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(name: &str, a: i64, b: i64, word: &str) -> i64 {
+        let op = ARITHMETIC.iter().find(|op| op.0 == name).unwrap();
+        let word = Word::from_name(word);
+        match arithmetic(*op, &Value::Int(a), &Value::Int(b), word).unwrap() {
+            Value::Int(i) => i,
+            v => panic!("expected an integer, got {:?}", v),
+        }
+    }
+
+    #[test]
+    fn every_step_wraps() {
+        // (200 + 100) / 2
+        let sum = apply("bit::add", 200, 100, "u8");
+        assert_eq!(sum, 44);
+        assert_eq!(apply("bit::div", sum, 2, "u8"), 22);
+        assert_eq!(apply("bit::sub", 0, 1, "u8"), 255);
+        assert_eq!(apply("bit::mul", 16, 16, "u16"), 256);
+        assert_eq!(apply("bit::mul", 256, 256, "u16"), 0);
+    }
+
+    #[test]
+    fn signed_words_overflow_into_negatives() {
+        assert_eq!(apply("bit::add", 127, 1, "i8"), -128);
+        assert_eq!(apply("bit::sub", -128, 1, "i8"), 127);
+        assert_eq!(apply("bit::mul", 64, 2, "i8"), -128);
+        assert_eq!(apply("bit::div", -128, -1, "i8"), -128);
+        assert_eq!(apply("bit::rem", -7, 2, "i8"), -1);
+        assert_eq!(apply("bit::add", i64::MAX, 1, "i64"), i64::MIN);
+    }
+
+    #[test]
+    fn unsigned_division() {
+        // 0xFF / 2 in a u8 is 127, not -1 / 2
+        assert_eq!(apply("bit::div", -1, 2, "u8"), 127);
+        assert_eq!(apply("bit::div", -1, 2, "u64"), i64::MAX);
+        assert_eq!(apply("bit::rem", -1, 16, "u64"), 15);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let div = ARITHMETIC.iter().find(|op| op.0 == "bit::div").unwrap();
+        let u8 = Word::from_name("u8");
+        assert!(arithmetic(*div, &Value::Int(1), &Value::Int(256), u8).is_err());
+        assert_eq!(
+            arithmetic(*div, &Value::Float(1.0), &Value::Int(4), u8),
+            Ok(Value::Float(0.25))
+        );
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            format(10, Word::from_name("u8").unwrap()),
+            "10 = 0x0A = 0b0000_1010"
+        );
+        assert_eq!(
+            format(-1, Word::from_name("i8").unwrap()),
+            "-1 = 0xFF = 0b1111_1111"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use {
+    gio::SettingsExt,
+    gtk::{RecentManager, RecentManagerExt},
+    std::path::{Path, PathBuf},
+};
+
+const PINNED_KEY: &str = "pinned-documents";
+const EXT: &str = "mee";
+
+/// Recent, unpinned, documents listed at most
+const LIMIT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub path: PathBuf,
+    pub pinned: bool,
+}
+
+impl Document {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn dir(&self) -> String {
+        self.path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Record `path` as recently used
+pub fn add(path: &Path) -> Result<(), String> {
+    let uri = glib::filename_to_uri(path, None).map_err(|e| e.to_string())?;
+    if let Some(manager) = RecentManager::get_default() {
+        manager.add_item(&uri);
+    }
+    Ok(())
+}
+
+/// Pinned documents first, then the most recently used ones
+pub fn documents(settings: &gio::Settings) -> Vec<Document> {
+    let pinned = pinned(settings);
+
+    let mut recent = RecentManager::get_default()
+        .map(|m| m.get_items())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|info| info.is_local() && info.exists())
+        .filter_map(|info| {
+            let uri = info.get_uri()?;
+            let (path, _) = glib::filename_from_uri(&uri).ok()?;
+            Some((info.get_modified(), path))
+        })
+        .filter(|(_, p)| p.extension().map(|e| e == EXT).unwrap_or(false) && !pinned.contains(p))
+        .collect::<Vec<_>>();
+    recent.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    pinned
+        .into_iter()
+        .filter(|p| p.exists())
+        .map(|path| Document { path, pinned: true })
+        .chain(recent.into_iter().take(LIMIT).map(|(_, path)| Document {
+            path,
+            pinned: false,
+        }))
+        .collect()
+}
+
+pub fn pinned(settings: &gio::Settings) -> Vec<PathBuf> {
+    settings
+        .get_strv(PINNED_KEY)
+        .iter()
+        .map(|p| PathBuf::from(p.as_str()))
+        .collect()
+}
+
+pub fn set_pinned(settings: &gio::Settings, path: &Path, pin: bool) -> Result<(), String> {
+    let mut pinned = pinned(settings);
+    pinned.retain(|p| p != path);
+    if pin {
+        pinned.push(path.to_path_buf());
+    }
+
+    let pinned = pinned
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    settings
+        .set_strv(
+            PINNED_KEY,
+            &pinned.iter().map(String::as_str).collect::<Vec<_>>(),
+        )
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,144 @@
+use std::{
+    cell::RefCell,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Seconds between snapshots of a dirty document
+pub const INTERVAL: u32 = 10;
+
+const CONTENT_EXT: &str = "mee";
+const PATH_EXT: &str = "path";
+
+pub fn dir() -> PathBuf {
+    glib::get_user_data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("math-expr-eval")
+        .join("recovery")
+}
+
+/// Snapshot of an unsaved document, `<pid>-<id>.mee` with the original path in `<pid>-<id>.path`.
+/// The content file stays locked while its snapshot is in use, a snapshot nobody holds the lock
+/// of was left behind by a process that is gone
+#[derive(Debug)]
+pub struct Snapshot {
+    name: String,
+    lock: RefCell<Option<File>>,
+}
+
+impl Snapshot {
+    /// A new snapshot owned by this process
+    pub fn create() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Self {
+            name: format!("{}-{}", std::process::id(), nanos),
+            lock: RefCell::new(None),
+        }
+    }
+
+    /// Snapshots left behind by processes that are no longer running, locked so no other
+    /// process offers them too
+    pub fn orphaned() -> Vec<Self> {
+        let mut snapshots = fs::read_dir(dir())
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().map(|e| e == CONTENT_EXT).unwrap_or(false))
+                    .filter_map(|p| {
+                        let lock = lock(OpenOptions::new().read(true).write(true).open(&p).ok()?)?;
+                        // Discarded by its owner while it was being locked
+                        if !p.exists() {
+                            return None;
+                        }
+                        Some(Self {
+                            name: p.file_stem()?.to_string_lossy().to_string(),
+                            lock: RefCell::new(Some(lock)),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        // Newest first
+        snapshots.sort_by_key(|s| std::cmp::Reverse(s.modified()));
+        snapshots
+    }
+
+    pub fn save(&self, content: &str, original: Option<&Path>) -> io::Result<()> {
+        if self.lock.borrow().is_none() {
+            fs::create_dir_all(dir())?;
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(self.file(CONTENT_EXT))?;
+            let lock = lock(file).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "The snapshot is in use elsewhere",
+                )
+            })?;
+            self.lock.replace(Some(lock));
+        }
+        if let Some(file) = self.lock.borrow_mut().as_mut() {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(content.as_bytes())?;
+        }
+        match original {
+            Some(p) => fs::write(self.file(PATH_EXT), p.to_string_lossy().as_bytes()),
+            None => self.remove_file(PATH_EXT),
+        }
+    }
+
+    pub fn content(&self) -> io::Result<String> {
+        fs::read_to_string(self.file(CONTENT_EXT))
+    }
+
+    /// Path of the document the snapshot was taken of, `None` if it was untitled
+    pub fn original(&self) -> Option<PathBuf> {
+        fs::read_to_string(self.file(PATH_EXT))
+            .ok()
+            .map(PathBuf::from)
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(self.file(CONTENT_EXT))
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Remove the snapshot, there is nothing left to recover
+    pub fn discard(&self) -> io::Result<()> {
+        let removed = self
+            .remove_file(CONTENT_EXT)
+            .and(self.remove_file(PATH_EXT));
+        // Unlocked once the files are gone, so no other process takes it for orphaned
+        self.lock.replace(None);
+        removed
+    }
+
+    fn file(&self, ext: &str) -> PathBuf {
+        dir().join(format!("{}.{}", self.name, ext))
+    }
+
+    fn remove_file(&self, ext: &str) -> io::Result<()> {
+        match fs::remove_file(self.file(ext)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `file` with an exclusive lock on it, `None` if another process holds one
+fn lock(file: File) -> Option<File> {
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } {
+        0 => Some(file),
+        _ => None,
+    }
+}
@@ -0,0 +1,142 @@
+use crate::eval::{self, EvalOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+    Text,
+}
+
+impl ReportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "markdown" => Some(Self::Markdown),
+            "html" => Some(Self::Html),
+            "text" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Text => "txt",
+        }
+    }
+}
+
+/// Every statement of `source` with its result shown the way the app shows it, empty for
+/// statements without a value
+fn rows(source: &str, options: EvalOptions) -> Vec<(String, String)> {
+    let shown = options.with_directives(source);
+    eval::eval_statements(source, options)
+        .into_iter()
+        .map(|statement| {
+            let result = match statement.result {
+                Ok((evalexpr::Value::Empty, _)) => String::new(),
+                Ok((value, kind)) => eval::format_value(&value, &kind, &shown),
+                Err(e) => format!("Error: {}", e),
+            };
+            (statement.source, result)
+        })
+        .collect()
+}
+
+/// The document `source` with the result of each statement, as a report titled `title`
+pub fn render(source: &str, options: EvalOptions, title: &str, format: ReportFormat) -> String {
+    let rows = rows(source, options);
+    match format {
+        ReportFormat::Markdown => markdown(title, &rows),
+        ReportFormat::Html => html(title, &rows),
+        ReportFormat::Text => text(title, &rows),
+    }
+}
+
+fn markdown(title: &str, rows: &[(String, String)]) -> String {
+    let cell = |s: &str| {
+        s.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace('|', "\\|")
+    };
+    let mut out = format!("# {}\n\n| Expression | Result |\n| --- | --- |\n", title);
+    for (source, result) in rows {
+        out.push_str(&format!("| {} | {} |\n", code(&cell(source)), cell(result)));
+    }
+    out
+}
+
+/// `text` as inline code, fenced by more backticks than it has in a row so it can hold some
+fn code(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest + 1);
+    // Backticks next to the fence would be taken as part of it
+    let padding = if text.starts_with('`') || text.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!("{0}{1}{2}{1}{0}", fence, padding, text)
+}
+
+fn html(title: &str, rows: &[(String, String)]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<table>\n<tr><th>Expression</th><th>Result</th></tr>\n",
+        escape_html(title)
+    );
+    for (source, result) in rows {
+        out.push_str(&format!(
+            "<tr><td><pre>{}</pre></td><td>{}</td></tr>\n",
+            escape_html(source),
+            escape_html(result)
+        ));
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+fn text(title: &str, rows: &[(String, String)]) -> String {
+    let mut out = format!("{}\n{}\n\n", title, "=".repeat(title.chars().count()));
+    for (source, result) in rows {
+        out.push_str(source);
+        out.push('\n');
+        if !result.is_empty() {
+            out.push_str(&format!("    = {}\n", result));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_fences() {
+        assert_eq!(code("x + 1"), "`x + 1`");
+        assert_eq!(code("a``b"), "```a``b```");
+        assert_eq!(code("`s`"), "`` `s` ``");
+    }
+
+    #[test]
+    fn markdown_cells() {
+        let rows = [(String::from("a |\n b"), String::from("1"))];
+        assert_eq!(
+            markdown("T", &rows),
+            "# T\n\n| Expression | Result |\n| --- | --- |\n| `a \\| b` | 1 |\n"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use {
+    crate::closure::Closure,
+    evalexpr::{EvalexprError, EvalexprResult, Value},
+};
+
+/// Most values a range, `sum_over` or `product_over` goes through
+const MAX_LENGTH: i128 = 1_000_000;
+
+fn error<T>(message: String) -> EvalexprResult<T> {
+    Err(EvalexprError::CustomMessage(message))
+}
+
+/// Elements of a tuple, or a single value as a list of one
+fn elements(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Tuple(t) => t.clone(),
+        Value::Empty => Vec::new(),
+        v => vec![v.clone()],
+    }
+}
+
+fn too_long<T>(length: i128) -> EvalexprResult<T> {
+    error(format!(
+        "Expected at most {} values, got {}",
+        MAX_LENGTH, length
+    ))
+}
+
+/// The closure and the other arguments of a higher-order function
+fn closure_args(value: &Value) -> EvalexprResult<(Closure, Vec<Value>)> {
+    let args = value.as_tuple()?;
+    let (closure, rest) = Closure::from_args(&args)?;
+    Ok((closure, rest.to_vec()))
+}
+
+/// Call `closure` with an element, tuples are spread over its variables when it has several:
+/// `map((a, b) -> a * b, zip(xs, ys))`
+fn apply(closure: &mut Closure, element: &Value) -> EvalexprResult<Value> {
+    match element {
+        Value::Tuple(t) if closure.params().len() > 1 && t.len() == closure.params().len() => {
+            closure.call(t)
+        }
+        v => closure.call(&[v.clone()]),
+    }
+}
+
+/// Sum or product of `values`, integers stay integers unless the result overflows
+fn combine(
+    values: &[Value],
+    identity: i64,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> EvalexprResult<Value> {
+    let ints = values.iter().try_fold(identity, |acc, v| match v {
+        Value::Int(i) => int(acc, *i),
+        _ => None,
+    });
+    match ints {
+        Some(i) => Ok(Value::Int(i)),
+        None => values
+            .iter()
+            .try_fold(identity as f64, |acc, v| Ok(float(acc, v.as_number()?)))
+            .map(Value::Float),
+    }
+}
+
+/// `range(from, to, [step])`, the numbers from `from` up to and including `to`. `a..b` and
+/// `a..b step s` are rewritten into this.
+pub fn range(value: &Value) -> EvalexprResult<Value> {
+    let args = elements(value);
+    let (from, to, step) = match args.as_slice() {
+        [from, to] => (from, to, &Value::Int(1)),
+        [from, to, step] => (from, to, step),
+        _ => {
+            return Err(EvalexprError::WrongFunctionArgumentAmount {
+                expected: 2,
+                actual: args.len(),
+            })
+        }
+    };
+
+    match (from, to, step) {
+        (Value::Int(from), Value::Int(to), Value::Int(step)) => {
+            if *step == 0 {
+                return error(String::from("The step of a range can not be 0"));
+            }
+            let span = *to as i128 - *from as i128;
+            let length = match span.signum() == (*step as i128).signum() || span == 0 {
+                true => span / *step as i128 + 1,
+                false => 0,
+            };
+            if length > MAX_LENGTH {
+                return too_long(length);
+            }
+            Ok(Value::Tuple(
+                (0..length as i64)
+                    .map(|i| Value::Int(from + i * step))
+                    .collect(),
+            ))
+        }
+        _ => {
+            let (from, to, step) = (from.as_number()?, to.as_number()?, step.as_number()?);
+            if step == 0.0 || !step.is_finite() {
+                return error(format!("Expected a finite step other than 0, got {}", step));
+            }
+            // Allow for rounding, so `0..1 step 0.1` ends at 1
+            let length = ((to - from) / step + 1e-9).floor() + 1.0;
+            if !(length <= MAX_LENGTH as f64) {
+                return too_long(length as i128);
+            }
+            Ok(Value::Tuple(
+                (0..length.max(0.0) as i64)
+                    .map(|i| Value::Float(from + i as f64 * step))
+                    .collect(),
+            ))
+        }
+    }
+}
+
+/// `seq::map(f, list)`, `f` applied to each element, the preprocessor turns `map(…)` into this
+pub fn map(value: &Value) -> EvalexprResult<Value> {
+    match closure_args(value)? {
+        (mut f, rest) if rest.len() == 1 => Ok(Value::Tuple(
+            elements(&rest[0])
+                .iter()
+                .map(|e| apply(&mut f, e))
+                .collect::<EvalexprResult<_>>()?,
+        )),
+        _ => error(String::from(
+            "Expected a function and a list, like map(x -> x^2, 1..10)",
+        )),
+    }
+}
+
+/// `seq::filter(f, list)`, the elements for which `f` is true
+pub fn filter(value: &Value) -> EvalexprResult<Value> {
+    match closure_args(value)? {
+        (mut f, rest) if rest.len() == 1 => {
+            let mut kept = Vec::new();
+            for e in elements(&rest[0]) {
+                if apply(&mut f, &e)?.as_boolean()? {
+                    kept.push(e);
+                }
+            }
+            Ok(Value::Tuple(kept))
+        }
+        _ => error(String::from(
+            "Expected a function and a list, like filter(x -> x > 0, xs)",
+        )),
+    }
+}
+
+/// `seq::reduce(f, list, [initial])`, the elements combined by `f(acc, x)` from the first one or
+/// `initial`
+pub fn reduce(value: &Value) -> EvalexprResult<Value> {
+    let (mut f, rest) = closure_args(value)?;
+    if f.params().len() != 2 {
+        return error(String::from(
+            "Expected a function of two variables, like (acc, x) -> acc + x",
+        ));
+    }
+    let (mut elements, initial) = match rest.as_slice() {
+        [list] => (elements(list).into_iter(), None),
+        [list, initial] => (elements(list).into_iter(), Some(initial.clone())),
+        _ => {
+            return error(String::from(
+                "Expected a function, a list and optionally an initial value",
+            ))
+        }
+    };
+    let mut acc = match initial.or_else(|| elements.next()) {
+        Some(acc) => acc,
+        None => return error(String::from("Can not reduce an empty list")),
+    };
+    for e in elements {
+        acc = f.call(&[acc, e])?;
+    }
+    Ok(acc)
+}
+
+/// The values of the closure of `sum_over` and `product_over` for its variable going from the
+/// first to the second argument
+fn over(value: &Value) -> EvalexprResult<Vec<Value>> {
+    let (mut f, rest) = closure_args(value)?;
+    let (from, to) = match rest.as_slice() {
+        [from, to] => (from.as_number()?, to.as_number()?),
+        _ => {
+            return error(String::from(
+                "Expected a variable, two bounds and an expression",
+            ))
+        }
+    };
+    if from.fract() != 0.0 || to.fract() != 0.0 {
+        return error(format!(
+            "Expected whole numbers as the bounds, got {} and {}",
+            from, to
+        ));
+    }
+    let length = (to - from + 1.0).max(0.0);
+    if length > MAX_LENGTH as f64 {
+        return too_long(length as i128);
+    }
+    (0..length as i64)
+        .map(|i| f.call(&[Value::Int(from as i64 + i)]))
+        .collect()
+}
+
+/// `seq::sum_over(i, from, to, expression)`, the sum of `expression` for `i` from `from` to `to`
+pub fn sum_over(value: &Value) -> EvalexprResult<Value> {
+    combine(&over(value)?, 0, i64::checked_add, |a, b| a + b)
+}
+
+/// `seq::product_over(i, from, to, expression)`, like `sum_over` for products
+pub fn product_over(value: &Value) -> EvalexprResult<Value> {
+    combine(&over(value)?, 1, i64::checked_mul, |a, b| a * b)
+}
+
+/// `sort(list)`, numbers or strings in ascending order
+pub fn sort(value: &Value) -> EvalexprResult<Value> {
+    let mut elements = elements(value);
+    if elements.iter().all(|e| matches!(e, Value::String(_))) {
+        elements.sort_by_key(|e| e.as_string().unwrap_or_default());
+    } else {
+        let mut keyed = elements
+            .into_iter()
+            .map(|e| match e.as_number()? {
+                n if n.is_nan() => error(String::from("Cannot sort a list containing NaN")),
+                n => Ok((n, e)),
+            })
+            .collect::<EvalexprResult<Vec<_>>>()?;
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        elements = keyed.into_iter().map(|(_, e)| e).collect();
+    }
+    Ok(Value::Tuple(elements))
+}
+
+/// `reverse(list)`
+pub fn reverse(value: &Value) -> EvalexprResult<Value> {
+    let mut elements = elements(value);
+    elements.reverse();
+    Ok(Value::Tuple(elements))
+}
+
+/// `zip(a, b, …)`, tuples of the elements at the same position in each list, as long as the
+/// shortest list
+pub fn zip(value: &Value) -> EvalexprResult<Value> {
+    let lists = value
+        .as_tuple()?
+        .iter()
+        .map(Value::as_tuple)
+        .collect::<EvalexprResult<Vec<_>>>()?;
+    let length = lists.iter().map(Vec::len).min().unwrap_or(0);
+    Ok(Value::Tuple(
+        (0..length)
+            .map(|i| Value::Tuple(lists.iter().map(|l| l[i].clone()).collect()))
+            .collect(),
+    ))
+}
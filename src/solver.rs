@@ -0,0 +1,374 @@
+use {
+    crate::closure::Closure,
+    evalexpr::{EvalexprError, EvalexprResult, Value},
+};
+
+/// Interval searched by `solve` when none is given
+const DEFAULT_INTERVAL: (f64, f64) = (-100.0, 100.0);
+/// Points of the interval where `solve` evaluates the equation, looking for sign changes
+const SAMPLES: usize = 4000;
+/// How close Newton's method gets to roots, relative to them or absolute below 1
+const TOLERANCE: f64 = 1e-14;
+
+fn error<T>(message: String) -> EvalexprResult<T> {
+    Err(EvalexprError::CustomMessage(message))
+}
+
+/// `closure` as a function of one number. Evaluation errors, like the logarithm of a negative
+/// number, are NaN and the first one is kept in `first_error`.
+fn function<'a>(
+    closure: &'a mut Closure,
+    first_error: &'a mut Option<EvalexprError>,
+) -> impl FnMut(f64) -> f64 + 'a {
+    move |x| match closure.number(&[x]) {
+        Ok(y) => y,
+        Err(e) => {
+            first_error.get_or_insert(e);
+            f64::NAN
+        }
+    }
+}
+
+/// Drop floating point noise, so the root of `x^2 - 4` is 2 rather than 1.9999999999999998.
+/// Numbers within `zero` of 0, the error of what found them, are 0, so the touching root of
+/// `x^2` is 0 rather than 1e-15, while the root of `x - 1e-13` stays 1e-13.
+pub fn tidy(x: f64, zero: f64) -> f64 {
+    let rounded = x.round();
+    if x.abs() <= zero {
+        0.0
+    } else if (x - rounded).abs() <= 1e-12 * x.abs() {
+        rounded + 0.0
+    } else {
+        x
+    }
+}
+
+/// Narrow down a sign change of `f` between `a` and `b` by bisection
+fn bisect(f: &mut impl FnMut(f64) -> f64, mut a: f64, mut b: f64) -> f64 {
+    let negative = f(a) < 0.0;
+    for _ in 0..200 {
+        let mid = a + (b - a) / 2.0;
+        if mid == a || mid == b {
+            break;
+        }
+        match f(mid) {
+            y if y == 0.0 => return mid,
+            y if (y < 0.0) == negative => a = mid,
+            _ => b = mid,
+        }
+    }
+    a + (b - a) / 2.0
+}
+
+/// Newton's method from `x`, `None` when it does not converge
+fn newton(f: &mut impl FnMut(f64) -> f64, mut x: f64) -> Option<f64> {
+    for _ in 0..100 {
+        let y = f(x);
+        if y == 0.0 {
+            return Some(x);
+        }
+        let h = 1e-7 * x.abs().max(1.0);
+        let slope = (f(x + h) - f(x - h)) / (2.0 * h);
+        let next = x - y / slope;
+        if !next.is_finite() {
+            return None;
+        }
+        if (next - x).abs() <= TOLERANCE * next.abs().max(1.0) {
+            return Some(next);
+        }
+        x = next;
+    }
+    None
+}
+
+/// Roots of `f` between `from` and `to`. Sign changes between samples are narrowed down by
+/// bisection, and samples closest to zero without a sign change by Newton's method, which
+/// finds roots that touch zero without crossing it, like the one of `x^2`.
+fn roots(f: &mut impl FnMut(f64) -> f64, from: f64, to: f64) -> Vec<f64> {
+    let step = (to - from) / SAMPLES as f64;
+    let samples = (0..=SAMPLES)
+        .map(|i| {
+            let x = from + step * i as f64;
+            (x, f(x))
+        })
+        .collect::<Vec<_>>();
+
+    let mut roots = samples
+        .iter()
+        .filter(|(_, y)| *y == 0.0)
+        .map(|(x, _)| *x)
+        .collect::<Vec<_>>();
+    for pair in samples.windows(2) {
+        let ((a, fa), (b, fb)) = (pair[0], pair[1]);
+        if fa.is_finite() && fb.is_finite() && fa * fb < 0.0 {
+            let root = bisect(f, a, b);
+            // A sign change across a pole, like the ones of `tan(x)`, is not a root
+            if f(root).abs() <= fa.abs().max(fb.abs()) {
+                roots.push(root);
+            }
+        }
+    }
+    for near in samples.windows(3) {
+        let ((before, fb), (x, fx), (after, fa)) = (near[0], near[1], near[2]);
+        if fb * fx > 0.0 && fx * fa > 0.0 && fx.abs() < fb.abs() && fx.abs() <= fa.abs() {
+            match newton(f, x) {
+                Some(root)
+                    if root > before
+                        && root < after
+                        && f(root).abs() <= 1e-10 * fb.abs().max(fa.abs()).max(1.0) =>
+                {
+                    roots.push(root)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    roots.dedup_by(|a, b| (*a - *b).abs() <= 1e-9 * a.abs().max(1.0));
+    roots.into_iter().map(|x| tidy(x, TOLERANCE)).collect()
+}
+
+/// Solve the linear system of the augmented matrix `rows` by Gaussian elimination, `None` when
+/// it has no unique solution
+fn eliminate(mut rows: Vec<Vec<f64>>) -> Option<Vec<f64>> {
+    let n = rows.len();
+    let largest = rows
+        .iter()
+        .flat_map(|row| row[..n].iter())
+        .fold(0.0, |max: f64, x| max.max(x.abs()));
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| {
+            rows[a][col]
+                .abs()
+                .partial_cmp(&rows[b][col].abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+        if !(rows[pivot][col].abs() > 1e-12 * largest) {
+            return None;
+        }
+        rows.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = rows[row][col] / rows[col][col];
+            for k in col..=n {
+                rows[row][k] -= factor * rows[col][k];
+            }
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|k| rows[row][k] * x[k]).sum();
+        x[row] = (rows[row][n] - known) / rows[row][row];
+    }
+    Some(x)
+}
+
+/// Solve linear equations in several variables, `closure` gives the residual of each equation
+fn linear_system(closure: &mut Closure) -> EvalexprResult<Value> {
+    let n = closure.params().len();
+    let mut residuals = |x: &[f64]| -> EvalexprResult<Vec<f64>> {
+        let args = x.iter().map(|x| Value::Float(*x)).collect::<Vec<_>>();
+        match closure.call(&args)? {
+            Value::Tuple(t) => t.iter().map(Value::as_number).collect(),
+            v => Ok(vec![v.as_number()?]),
+        }
+    };
+
+    let at_origin = residuals(&vec![0.0; n])?;
+    if at_origin.len() != n {
+        return error(format!(
+            "Expected {} equations, one for each variable, got {}",
+            n,
+            at_origin.len()
+        ));
+    }
+    // Row `i` of the matrix is how equation `i` changes with each variable
+    let mut rows = vec![vec![0.0; n + 1]; n];
+    for j in 0..n {
+        let mut x = vec![0.0; n];
+        x[j] = 1.0;
+        for (i, r) in residuals(&x)?.into_iter().enumerate() {
+            rows[i][j] = r - at_origin[i];
+        }
+    }
+    for (row, r) in rows.iter_mut().zip(&at_origin) {
+        row[n] = -r;
+    }
+
+    // Equations that are not linear make the matrix wrong, so check it predicts them elsewhere
+    // and that the solution solves them
+    let scale = at_origin.iter().fold(1.0, |max: f64, r| max.max(r.abs()));
+    let ones = residuals(&vec![1.0; n])?;
+    let predicted = rows.iter().map(|row| row[..n].iter().sum::<f64>() - row[n]);
+    let linear = ones
+        .iter()
+        .zip(predicted)
+        .all(|(r, p)| (r - p).abs() <= 1e-9 * scale.max(p.abs()));
+    let solution = match (linear, eliminate(rows)) {
+        (true, Some(x)) if residuals(&x)?.iter().all(|r| r.abs() <= 1e-9 * scale) => x,
+        (true, None) => return error(String::from("The equations have no unique solution")),
+        _ => {
+            return error(format!(
+                "The equations are not linear in {}",
+                closure.params().join(", ")
+            ))
+        }
+    };
+    Ok(Value::Tuple(
+        solution
+            .into_iter()
+            .map(|x| Value::Float(tidy(x, TOLERANCE)))
+            .collect(),
+    ))
+}
+
+/// `num::solve(equation, x, [from, to])`, the real solutions of an equation in `x` between
+/// `from` and `to`, or of linear equations in several variables:
+/// `num::solve((x + y == 3, x - y == 1), (x, y))`. The preprocessor turns `solve(…)` into this.
+pub fn solve(value: &Value) -> EvalexprResult<Value> {
+    let args = value.as_tuple()?;
+    let (mut closure, rest) = Closure::from_args(&args)?;
+    let (from, to) = match rest {
+        [] => DEFAULT_INTERVAL,
+        [from, to] if closure.params().len() == 1 => (from.as_number()?, to.as_number()?),
+        _ => {
+            return error(String::from(
+                "Expected an equation, its variables and optionally an interval to search",
+            ))
+        }
+    };
+    if closure.params().len() > 1 {
+        return linear_system(&mut closure);
+    }
+    if !(from < to) {
+        return error(format!("Expected an interval, got {} to {}", from, to));
+    }
+
+    let mut first_error = None;
+    let roots = roots(&mut function(&mut closure, &mut first_error), from, to);
+    match (roots.as_slice(), first_error) {
+        ([root], _) => Ok(Value::Float(*root)),
+        ([], Some(e)) => Err(e),
+        ([], None) => error(format!(
+            "No solution for {} between {} and {}",
+            closure.params()[0],
+            from,
+            to
+        )),
+        (roots, _) => Ok(Value::Tuple(
+            roots.iter().map(|x| Value::Float(*x)).collect(),
+        )),
+    }
+}
+
+/// `num::root(f, x0)`, a root of `f`, an expression in `x`, close to `x0`. Newton's method is
+/// tried first, then bisection of a sign change found searching outwards from `x0`.
+pub fn root(value: &Value) -> EvalexprResult<Value> {
+    let args = value.as_tuple()?;
+    let (mut closure, rest) = Closure::from_args(&args)?;
+    let x0 = match rest {
+        [x0] => x0.as_number()?,
+        _ => {
+            return Err(EvalexprError::WrongFunctionArgumentAmount {
+                expected: 2,
+                actual: rest.len() + 1,
+            })
+        }
+    };
+    let y0 = closure.number(&[x0])?;
+
+    let mut first_error = None;
+    let mut f = function(&mut closure, &mut first_error);
+    if let Some(root) = newton(&mut f, x0) {
+        if f(root).abs() <= 1e-9 * y0.abs().max(1.0) {
+            return Ok(Value::Float(tidy(root, TOLERANCE)));
+        }
+    }
+
+    let mut step = 0.01 * x0.abs().max(1.0);
+    let (mut left, mut right) = ((x0, y0), (x0, y0));
+    for _ in 0..64 {
+        let (a, b) = (x0 - step, x0 + step);
+        let outwards = [(left, (a, f(a))), (right, (b, f(b)))];
+        for &((x1, y1), (x2, y2)) in &outwards {
+            if y1.is_finite() && y2.is_finite() && y1 * y2 <= 0.0 {
+                let root = bisect(&mut f, x1, x2);
+                if f(root).abs() <= y1.abs().max(y2.abs()) {
+                    return Ok(Value::Float(tidy(root, TOLERANCE)));
+                }
+            }
+        }
+        left = outwards[0].1;
+        right = outwards[1].1;
+        step *= 2.0;
+    }
+    error(format!("No root found near {}", x0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(actual: Vec<f64>, expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "roots {:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a - e).abs() <= 1e-9 * e.abs().max(1.0),
+                "roots {:?}",
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn crossing_roots() {
+        let sqrt2 = 2f64.sqrt();
+        assert_roots(roots(&mut |x| x * x - 2.0, -10.0, 10.0), &[-sqrt2, sqrt2]);
+        assert_eq!(roots(&mut |x| x * x - 4.0, -10.0, 10.0), vec![-2.0, 2.0]);
+        let pi = std::f64::consts::PI;
+        assert_roots(roots(&mut f64::sin, -4.0, 4.0), &[-pi, 0.0, pi]);
+        assert_eq!(roots(&mut |x| (x - 1.0).powi(3), -10.0, 10.0), vec![1.0]);
+        assert_eq!(roots(&mut |x| x - 1e-13, -10.0, 10.0), vec![1e-13]);
+    }
+
+    #[test]
+    fn touching_roots() {
+        assert_eq!(roots(&mut |x| x * x, -10.0, 10.0), vec![0.0]);
+        assert_roots(roots(&mut |x| (x - 0.3).powi(2), -10.0, 10.0), &[0.3]);
+    }
+
+    #[test]
+    fn no_roots() {
+        assert!(roots(&mut |x| x * x + 1.0, -10.0, 10.0).is_empty());
+        // Sign changes at the poles of tan are not roots
+        assert_roots(roots(&mut f64::tan, -2.0, 2.0), &[0.0]);
+    }
+
+    #[test]
+    fn linear_systems() {
+        // x + y = 3, x - y = 1
+        assert_eq!(
+            eliminate(vec![vec![1.0, 1.0, 3.0], vec![1.0, -1.0, 1.0]]),
+            Some(vec![2.0, 1.0])
+        );
+        // Needs a row swap
+        assert_eq!(
+            eliminate(vec![vec![0.0, 2.0, 4.0], vec![3.0, 0.0, 3.0]]),
+            Some(vec![1.0, 2.0])
+        );
+        assert_eq!(
+            eliminate(vec![vec![1.0, 1.0, 1.0], vec![2.0, 2.0, 2.0]]),
+            None
+        );
+    }
+
+    #[test]
+    fn tidying() {
+        assert_eq!(tidy(1.9999999999999998, TOLERANCE), 2.0);
+        assert_eq!(tidy(-1e-15, TOLERANCE), 0.0);
+        assert_eq!(tidy(1e-13, TOLERANCE), 1e-13);
+        assert_eq!(tidy(1000.001, TOLERANCE), 1000.001);
+        assert_eq!(tidy(0.5, TOLERANCE), 0.5);
+    }
+}
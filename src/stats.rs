@@ -0,0 +1,312 @@
+use evalexpr::{EvalexprError, EvalexprResult, Value};
+
+/// Values of a data set given either as the arguments, `f(1, 2, 3)`, or as a single tuple,
+/// `f(data)`
+fn values(value: &Value) -> EvalexprResult<Vec<Value>> {
+    let values = match value {
+        Value::Tuple(t) => t.clone(),
+        v => vec![v.clone()],
+    };
+    if values.is_empty() {
+        return Err(EvalexprError::CustomMessage(String::from(
+            "Expected at least one value",
+        )));
+    }
+    Ok(values)
+}
+
+fn numbers(value: &Value) -> EvalexprResult<Vec<f64>> {
+    values(value)?.iter().map(Value::as_number).collect()
+}
+
+/// Numbers that can be ordered, NaN has no place in a sorted data set
+fn ordered(number: f64) -> EvalexprResult<f64> {
+    if number.is_nan() {
+        Err(EvalexprError::CustomMessage(String::from(
+            "Cannot order a data set containing NaN",
+        )))
+    } else {
+        Ok(number)
+    }
+}
+
+/// `numbers` sorted ascending
+fn sorted(value: &Value) -> EvalexprResult<Vec<f64>> {
+    let mut numbers = numbers(value)?
+        .into_iter()
+        .map(ordered)
+        .collect::<EvalexprResult<Vec<_>>>()?;
+    numbers.sort_by(f64::total_cmp);
+    Ok(numbers)
+}
+
+/// Split the arguments of `f(data, x)` into the data set and `x`
+fn data_and_number(value: &Value) -> EvalexprResult<(Vec<f64>, f64)> {
+    match value.as_tuple()?.as_slice() {
+        [data, x] => Ok((sorted(data)?, x.as_number()?)),
+        args => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 2,
+            actual: args.len(),
+        }),
+    }
+}
+
+/// Split the arguments of `f(xs, ys)` into two data sets of the same length
+fn paired(value: &Value) -> EvalexprResult<(Vec<f64>, Vec<f64>)> {
+    match value.as_tuple()?.as_slice() {
+        [xs, ys] => {
+            let (xs, ys) = (numbers(xs)?, numbers(ys)?);
+            if xs.len() != ys.len() {
+                return Err(EvalexprError::CustomMessage(format!(
+                    "Expected data sets of the same length, got {} and {} values",
+                    xs.len(),
+                    ys.len()
+                )));
+            }
+            if xs.len() < 2 {
+                return Err(EvalexprError::CustomMessage(String::from(
+                    "Expected at least two pairs of values",
+                )));
+            }
+            Ok((xs, ys))
+        }
+        args => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 2,
+            actual: args.len(),
+        }),
+    }
+}
+
+fn mean_of(numbers: &[f64]) -> f64 {
+    numbers.iter().sum::<f64>() / numbers.len() as f64
+}
+
+/// Sum of squared deviations from the mean
+fn squared_deviations(numbers: &[f64]) -> f64 {
+    let mean = mean_of(numbers);
+    numbers.iter().map(|x| (x - mean).powi(2)).sum()
+}
+
+fn sample_variance(value: &Value) -> EvalexprResult<f64> {
+    let numbers = numbers(value)?;
+    if numbers.len() < 2 {
+        return Err(EvalexprError::CustomMessage(String::from(
+            "The sample variance needs at least two values",
+        )));
+    }
+    Ok(squared_deviations(&numbers) / (numbers.len() - 1) as f64)
+}
+
+fn population_variance(value: &Value) -> EvalexprResult<f64> {
+    let numbers = numbers(value)?;
+    Ok(squared_deviations(&numbers) / numbers.len() as f64)
+}
+
+/// Quantile `q` of sorted data, interpolating linearly between the closest values
+fn quantile_of(sorted: &[f64], q: f64) -> EvalexprResult<f64> {
+    if !(0.0..=1.0).contains(&q) {
+        return Err(EvalexprError::CustomMessage(format!(
+            "Expected a quantile between 0 and 1, got {}",
+            q
+        )));
+    }
+    let position = q * (sorted.len() - 1) as f64;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    Ok(sorted[below] + (sorted[above] - sorted[below]) * position.fract())
+}
+
+pub fn sum(value: &Value) -> EvalexprResult<Value> {
+    let values = values(value)?;
+    // Integers stay integers, unless the sum overflows
+    let ints = values
+        .iter()
+        .map(|v| match v {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        })
+        .try_fold(0i64, |sum, i| sum.checked_add(i?));
+    match ints {
+        Some(sum) => Ok(Value::Int(sum)),
+        None => Ok(Value::Float(
+            values
+                .iter()
+                .map(Value::as_number)
+                .sum::<EvalexprResult<f64>>()?,
+        )),
+    }
+}
+
+pub fn mean(value: &Value) -> EvalexprResult<Value> {
+    Ok(Value::Float(mean_of(&numbers(value)?)))
+}
+
+pub fn median(value: &Value) -> EvalexprResult<Value> {
+    Ok(Value::Float(quantile_of(&sorted(value)?, 0.5)?))
+}
+
+/// The most common value, the smallest one when several are equally common
+pub fn mode(value: &Value) -> EvalexprResult<Value> {
+    let mut values = values(value)?
+        .into_iter()
+        .map(|v| Ok((ordered(v.as_number()?)?, v)))
+        .collect::<EvalexprResult<Vec<_>>>()?;
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut best = (0, 0);
+    let mut run_start = 0;
+    for i in 1..=values.len() {
+        if i == values.len() || values[i].0 != values[run_start].0 {
+            if i - run_start > best.1 {
+                best = (run_start, i - run_start);
+            }
+            run_start = i;
+        }
+    }
+    Ok(values.swap_remove(best.0).1)
+}
+
+pub fn variance(value: &Value) -> EvalexprResult<Value> {
+    sample_variance(value).map(Value::Float)
+}
+
+pub fn pvariance(value: &Value) -> EvalexprResult<Value> {
+    population_variance(value).map(Value::Float)
+}
+
+pub fn stddev(value: &Value) -> EvalexprResult<Value> {
+    sample_variance(value).map(|v| Value::Float(v.sqrt()))
+}
+
+pub fn pstddev(value: &Value) -> EvalexprResult<Value> {
+    population_variance(value).map(|v| Value::Float(v.sqrt()))
+}
+
+pub fn percentile(value: &Value) -> EvalexprResult<Value> {
+    let (data, p) = data_and_number(value)?;
+    if !(0.0..=100.0).contains(&p) {
+        return Err(EvalexprError::CustomMessage(format!(
+            "Expected a percentile between 0 and 100, got {}",
+            p
+        )));
+    }
+    quantile_of(&data, p / 100.0).map(Value::Float)
+}
+
+pub fn quantile(value: &Value) -> EvalexprResult<Value> {
+    let (data, q) = data_and_number(value)?;
+    quantile_of(&data, q).map(Value::Float)
+}
+
+/// Pearson correlation coefficient
+pub fn correlation(value: &Value) -> EvalexprResult<Value> {
+    let (xs, ys) = paired(value)?;
+    let (mx, my) = (mean_of(&xs), mean_of(&ys));
+    let covariance: f64 = xs.iter().zip(&ys).map(|(x, y)| (x - mx) * (y - my)).sum();
+    Ok(Value::Float(
+        covariance / (squared_deviations(&xs) * squared_deviations(&ys)).sqrt(),
+    ))
+}
+
+/// Least squares fit of `y = slope * x + intercept`, returned as `(slope, intercept)`
+pub fn linreg(value: &Value) -> EvalexprResult<Value> {
+    let (xs, ys) = paired(value)?;
+    let (mx, my) = (mean_of(&xs), mean_of(&ys));
+    let sxx = squared_deviations(&xs);
+    if sxx == 0.0 {
+        return Err(EvalexprError::CustomMessage(String::from(
+            "Can not fit a line when all x values are equal",
+        )));
+    }
+    let sxy: f64 = xs.iter().zip(&ys).map(|(x, y)| (x - mx) * (y - my)).sum();
+    let slope = sxy / sxx;
+    Ok(Value::Tuple(vec![
+        Value::Float(slope),
+        Value::Float(my - slope * mx),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(values: &[f64]) -> Value {
+        Value::Tuple(values.iter().map(|v| Value::Float(*v)).collect())
+    }
+
+    fn number(result: EvalexprResult<Value>) -> f64 {
+        result.unwrap().as_number().unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    const SAMPLE: &[f64] = &[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+
+    #[test]
+    fn averages() {
+        assert_eq!(
+            sum(&Value::Tuple(vec![Value::Int(1), Value::Int(2)])),
+            Ok(Value::Int(3))
+        );
+        assert_eq!(
+            sum(&Value::Tuple(vec![Value::Int(i64::MAX), Value::Int(1)])),
+            Ok(Value::Float(i64::MAX as f64 + 1.0))
+        );
+        assert_close(number(mean(&data(&[1.0, 2.0, 3.0, 4.0]))), 2.5);
+        assert_close(number(median(&data(&[3.0, 1.0, 4.0, 1.0, 5.0]))), 3.0);
+        assert_close(number(median(&data(&[4.0, 1.0, 3.0, 2.0]))), 2.5);
+        assert_close(number(mode(&data(&[1.0, 2.0, 2.0, 3.0]))), 2.0);
+        assert_close(number(mode(&data(&[2.0, 2.0, 1.0, 1.0]))), 1.0);
+    }
+
+    #[test]
+    fn spread() {
+        assert_close(number(variance(&data(SAMPLE))), 32.0 / 7.0);
+        assert_close(number(stddev(&data(SAMPLE))), (32.0f64 / 7.0).sqrt());
+        assert_close(number(pvariance(&data(SAMPLE))), 4.0);
+        assert_close(number(pstddev(&data(SAMPLE))), 2.0);
+        assert!(variance(&data(&[1.0])).is_err());
+    }
+
+    #[test]
+    fn quantiles() {
+        let args = |x| Value::Tuple(vec![data(&[1.0, 2.0, 3.0, 4.0, 5.0]), Value::Float(x)]);
+        // PERCENTILE.INC of spreadsheets
+        assert_close(number(percentile(&args(25.0))), 2.0);
+        assert_close(number(percentile(&args(90.0))), 4.6);
+        assert_close(number(quantile(&args(0.5))), 3.0);
+        assert!(percentile(&args(101.0)).is_err());
+        assert!(quantile(&args(-0.1)).is_err());
+    }
+
+    #[test]
+    fn regression() {
+        let pairs = |xs: &[f64], ys: &[f64]| Value::Tuple(vec![data(xs), data(ys)]);
+        assert_close(
+            number(correlation(&pairs(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]))),
+            1.0,
+        );
+        assert_close(
+            number(correlation(&pairs(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]))),
+            -1.0,
+        );
+        assert_eq!(
+            linreg(&pairs(&[1.0, 2.0, 3.0], &[3.0, 5.0, 7.0])),
+            Ok(Value::Tuple(vec![Value::Float(2.0), Value::Float(1.0)]))
+        );
+        assert!(linreg(&pairs(&[1.0, 1.0], &[1.0, 2.0])).is_err());
+        assert!(correlation(&pairs(&[1.0, 2.0], &[1.0])).is_err());
+    }
+
+    #[test]
+    fn nan_cannot_be_ordered() {
+        assert!(median(&data(&[1.0, f64::NAN, 2.0])).is_err());
+        assert!(mode(&data(&[f64::NAN])).is_err());
+    }
+}
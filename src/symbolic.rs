@@ -0,0 +1,574 @@
+use {
+    crate::{
+        angle::AngleUnit,
+        eval,
+        preprocess::{self, Token},
+    },
+    evalexpr::{EvalexprError, EvalexprResult, Value},
+    std::fmt,
+};
+
+/// Expression tree of the arithmetic subset of evalexpr that can be worked on symbolically
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Pow(Box<Expr>, Box<Expr>),
+    /// Function name as written, `sin` or `math::sin`, and its arguments
+    Call(String, Vec<Expr>),
+}
+
+use Expr::*;
+
+fn error<T>(message: String) -> EvalexprResult<T> {
+    Err(EvalexprError::CustomMessage(message))
+}
+
+fn num(n: f64) -> Expr {
+    Num(n)
+}
+
+fn neg(a: Expr) -> Expr {
+    Neg(Box::new(a))
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    Add(Box::new(a), Box::new(b))
+}
+
+fn sub(a: Expr, b: Expr) -> Expr {
+    Sub(Box::new(a), Box::new(b))
+}
+
+fn mul(a: Expr, b: Expr) -> Expr {
+    Mul(Box::new(a), Box::new(b))
+}
+
+fn div(a: Expr, b: Expr) -> Expr {
+    Div(Box::new(a), Box::new(b))
+}
+
+fn pow(a: Expr, b: Expr) -> Expr {
+    Pow(Box::new(a), Box::new(b))
+}
+
+/// Call `name` with the namespace of `like`, so `math::sin` differentiates to `math::cos`
+fn call(like: &str, name: &str, arg: Expr) -> Expr {
+    let namespace = like.rfind("::").map(|i| &like[..i + 2]).unwrap_or("");
+    Call(format!("{}{}", namespace, name), vec![arg])
+}
+
+/// `name` without its namespace
+fn base_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> EvalexprResult<Self> {
+        let tokens = preprocess::tokenize(source)
+            .into_iter()
+            .filter(|t| !matches!(t, Token::Space(_)))
+            .collect::<Vec<_>>();
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.sum()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(t) => error(format!("Unexpected \"{}\" in \"{}\"", t.text(), source)),
+        }
+    }
+
+    pub fn contains(&self, var: &str) -> bool {
+        match self {
+            Num(_) => false,
+            Var(v) => v == var,
+            Neg(a) => a.contains(var),
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Pow(a, b) => {
+                a.contains(var) || b.contains(var)
+            }
+            Call(_, args) => args.iter().any(|a| a.contains(var)),
+        }
+    }
+
+    /// Apply the `diff` and `simplify` calls inside the expression, with trigonometric
+    /// functions working in `angle`
+    pub fn expand(self, angle: AngleUnit) -> EvalexprResult<Self> {
+        let expand = |e: Box<Expr>| e.expand(angle);
+        Ok(match self {
+            Call(name, args) => {
+                let args = args
+                    .into_iter()
+                    .map(|a| a.expand(angle))
+                    .collect::<EvalexprResult<Vec<_>>>()?;
+                match (base_name(&name), args.as_slice()) {
+                    ("diff", [e, Var(x)]) => e.derivative(x, angle)?.simplify(),
+                    ("simplify", [e]) => e.clone().simplify(),
+                    ("diff", _) | ("simplify", _) => {
+                        return error(format!("Wrong arguments for {}", name))
+                    }
+                    _ => Call(name, args),
+                }
+            }
+            Neg(a) => neg(expand(a)?),
+            Add(a, b) => add(expand(a)?, expand(b)?),
+            Sub(a, b) => sub(expand(a)?, expand(b)?),
+            Mul(a, b) => mul(expand(a)?, expand(b)?),
+            Div(a, b) => div(expand(a)?, expand(b)?),
+            Pow(a, b) => pow(expand(a)?, expand(b)?),
+            e => e,
+        })
+    }
+
+    /// Derivative with respect to `x`, not simplified, with trigonometric functions working in
+    /// `angle`
+    pub fn derivative(&self, x: &str, angle: AngleUnit) -> EvalexprResult<Self> {
+        if !self.contains(x) {
+            return Ok(num(0.0));
+        }
+        let d = |e: &Expr| e.derivative(x, angle);
+        Ok(match self {
+            Num(_) => num(0.0),
+            Var(v) => num(if v == x { 1.0 } else { 0.0 }),
+            Neg(a) => neg(d(a)?),
+            Add(a, b) => add(d(a)?, d(b)?),
+            Sub(a, b) => sub(d(a)?, d(b)?),
+            Mul(a, b) => add(mul(d(a)?, (**b).clone()), mul((**a).clone(), d(b)?)),
+            Div(a, b) => div(
+                sub(mul(d(a)?, (**b).clone()), mul((**a).clone(), d(b)?)),
+                pow((**b).clone(), num(2.0)),
+            ),
+            // Power rule
+            Pow(a, b) if !b.contains(x) => mul(
+                mul(
+                    (**b).clone(),
+                    pow((**a).clone(), sub((**b).clone(), num(1.0))),
+                ),
+                d(a)?,
+            ),
+            // Exponential rule, `a^b = exp(b * ln(a))`
+            Pow(a, b) => mul(
+                self.clone(),
+                add(
+                    mul(d(b)?, Call(String::from("math::ln"), vec![(**a).clone()])),
+                    div(mul((**b).clone(), d(a)?), (**a).clone()),
+                ),
+            ),
+            Call(name, args) if args.len() == 1 => {
+                let u = args[0].clone();
+                let outer = match base_name(name) {
+                    "sin" => call(name, "cos", u),
+                    "cos" => neg(call(name, "sin", u)),
+                    "tan" => div(num(1.0), pow(call(name, "cos", u), num(2.0))),
+                    "asin" => div(
+                        num(1.0),
+                        call(name, "sqrt", sub(num(1.0), pow(u, num(2.0)))),
+                    ),
+                    "acos" => neg(div(
+                        num(1.0),
+                        call(name, "sqrt", sub(num(1.0), pow(u, num(2.0)))),
+                    )),
+                    "atan" => div(num(1.0), add(num(1.0), pow(u, num(2.0)))),
+                    "sinh" => call(name, "cosh", u),
+                    "cosh" => call(name, "sinh", u),
+                    "tanh" => div(num(1.0), pow(call(name, "cosh", u), num(2.0))),
+                    "exp" => self.clone(),
+                    "ln" => div(num(1.0), u),
+                    "log2" => div(num(1.0), mul(u, call(name, "ln", num(2.0)))),
+                    "log10" => div(num(1.0), mul(u, call(name, "ln", num(10.0)))),
+                    "sqrt" => div(num(1.0), mul(num(2.0), self.clone())),
+                    "cbrt" => div(num(1.0), mul(num(3.0), pow(self.clone(), num(2.0)))),
+                    "abs" => div(u, self.clone()),
+                    _ => return error(format!("Can not differentiate {}", name)),
+                };
+                // Radians in one unit of `angle`, `sin(x)` in degrees is `sin(PI / 180 * x)`
+                // in radians and `asin(x)` in degrees is `asin(x) / (PI / 180)`
+                let radians = || div(Var(String::from("PI")), num(angle.half_turn()));
+                let outer = match base_name(name) {
+                    _ if angle == AngleUnit::Rad => outer,
+                    "sin" | "cos" | "tan" => mul(outer, radians()),
+                    "asin" | "acos" | "atan" => div(outer, radians()),
+                    _ => outer,
+                };
+                // Chain rule
+                mul(outer, d(&args[0])?)
+            }
+            Call(name, _) => return error(format!("Can not differentiate {}", name)),
+        })
+    }
+
+    /// Fold constants and remove neutral elements until nothing changes
+    pub fn simplify(self) -> Self {
+        let mut expr = self;
+        for _ in 0..32 {
+            let next = expr.clone().simplify_once();
+            if next == expr {
+                break;
+            }
+            expr = next;
+        }
+        expr
+    }
+
+    fn simplify_once(self) -> Self {
+        match self {
+            Neg(a) => match a.simplify_once() {
+                Num(n) => num(-n),
+                Neg(a) => *a,
+                a => neg(a),
+            },
+            Add(a, b) => match (a.simplify_once(), b.simplify_once()) {
+                (Num(a), Num(b)) => num(a + b),
+                (Num(z), e) | (e, Num(z)) if z == 0.0 => e,
+                (a, Neg(b)) => sub(a, *b),
+                (Neg(a), b) => sub(b, *a),
+                (a, b) if a == b => mul(num(2.0), a),
+                (a, b) => match (coefficient(&a), coefficient(&b)) {
+                    ((m, x), (n, y)) if x == y => mul(num(m + n), x),
+                    _ => add(a, b),
+                },
+            },
+            Sub(a, b) => match (a.simplify_once(), b.simplify_once()) {
+                (Num(a), Num(b)) => num(a - b),
+                (e, Num(z)) if z == 0.0 => e,
+                (Num(z), e) if z == 0.0 => neg(e),
+                (a, Neg(b)) => add(a, *b),
+                (a, b) if a == b => num(0.0),
+                (a, b) => match (coefficient(&a), coefficient(&b)) {
+                    ((m, x), (n, y)) if x == y => mul(num(m - n), x),
+                    _ => sub(a, b),
+                },
+            },
+            Mul(a, b) => match (a.simplify_once(), b.simplify_once()) {
+                (Num(a), Num(b)) => num(a * b),
+                (Num(z), _) | (_, Num(z)) if z == 0.0 => num(0.0),
+                (Num(o), e) | (e, Num(o)) if o == 1.0 => e,
+                (Neg(a), b) | (b, Neg(a)) => neg(mul(*a, b)),
+                (a, Div(b, c)) | (Div(b, c), a) if *b == num(1.0) => div(a, *c),
+                // Constants first, and gathered together
+                (e, Num(n)) => mul(num(n), e),
+                (Num(m), Mul(a, b)) => match *a {
+                    Num(n) => mul(num(m * n), *b),
+                    a => mul(num(m), mul(a, *b)),
+                },
+                (a, Mul(b, c)) if matches!(*b, Num(_)) => mul(*b, mul(a, *c)),
+                (a, b) if a == b => pow(a, num(2.0)),
+                (a, b) => match (power(&a), power(&b)) {
+                    ((x, m), (y, n)) if x == y => pow(x, add(m, n)),
+                    _ => mul(a, b),
+                },
+            },
+            Div(a, b) => match (a.simplify_once(), b.simplify_once()) {
+                (Num(a), Num(b)) if b != 0.0 && (a / b).fract() == 0.0 => num(a / b),
+                (Num(z), _) if z == 0.0 => num(0.0),
+                (e, Num(o)) if o == 1.0 => e,
+                (Neg(a), b) => neg(div(*a, b)),
+                (a, b) if a == b => num(1.0),
+                (a, b) => match (power(&a), power(&b)) {
+                    ((x, m), (y, n)) if x == y => pow(x, sub(m, n)),
+                    _ => div(a, b),
+                },
+            },
+            Pow(a, b) => match (a.simplify_once(), b.simplify_once()) {
+                (Num(a), Num(b)) if a.powf(b).fract() == 0.0 => num(a.powf(b)),
+                (_, Num(z)) if z == 0.0 => num(1.0),
+                (e, Num(o)) if o == 1.0 => e,
+                (Num(o), _) if o == 1.0 => num(1.0),
+                // `(x^2)^0.5` is `|x|`, not `x`, powers only multiply for whole exponents or
+                // bases that are not negative
+                (Pow(a, m), Num(n)) if n.fract() == 0.0 => pow(*a, mul(*m, num(n))),
+                (Pow(a, m), n) if matches!(*a, Num(b) if b >= 0.0) => pow(*a, mul(*m, n)),
+                (a, b) => pow(a, b),
+            },
+            Call(name, args) => Call(name, args.into_iter().map(Expr::simplify_once).collect()),
+            e => e,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Add(..) | Sub(..) => 1,
+            Mul(..) | Div(..) => 2,
+            Neg(_) => 3,
+            Num(n) if *n < 0.0 => 3,
+            Pow(..) => 4,
+            _ => 5,
+        }
+    }
+}
+
+/// `e` as `coefficient * term`, so like terms can be added
+fn coefficient(e: &Expr) -> (f64, Expr) {
+    match e {
+        Mul(a, b) => match **a {
+            Num(n) => (n, (**b).clone()),
+            _ => (1.0, e.clone()),
+        },
+        e => (1.0, e.clone()),
+    }
+}
+
+/// `e` as `base ^ exponent`, so powers of the same base can be multiplied
+fn power(e: &Expr) -> (Expr, Expr) {
+    match e {
+        Pow(a, b) => ((**a).clone(), (**b).clone()),
+        e => (e.clone(), num(1.0)),
+    }
+}
+
+fn write_child(f: &mut fmt::Formatter, e: &Expr, parens: bool) -> fmt::Result {
+    if parens {
+        write!(f, "({})", e)
+    } else {
+        write!(f, "{}", e)
+    }
+}
+
+impl Expr {
+    fn write_binary(&self, f: &mut fmt::Formatter, a: &Expr, op: &str, b: &Expr) -> fmt::Result {
+        let p = self.precedence();
+        write_child(f, a, a.precedence() < p)?;
+        write!(f, " {} ", op)?;
+        // `a - (b - c)` and `a / (b * c)` need their parentheses, `a + (b + c)` does not
+        let same = matches!((self, b), (Add(..), Add(..)) | (Mul(..), Mul(..)));
+        write_child(f, b, b.precedence() < p || (b.precedence() == p && !same))
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Num(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Num(n) => write!(f, "{}", n),
+            Var(v) => write!(f, "{}", v),
+            Neg(a) => {
+                write!(f, "-")?;
+                write_child(f, a, a.precedence() < 4)
+            }
+            Add(a, b) => self.write_binary(f, a, "+", b),
+            Sub(a, b) => self.write_binary(f, a, "-", b),
+            Mul(a, b) => self.write_binary(f, a, "*", b),
+            Div(a, b) => self.write_binary(f, a, "/", b),
+            Pow(a, b) => {
+                write_child(f, a, a.precedence() <= 4)?;
+                write!(f, "^")?;
+                write_child(f, b, b.precedence() <= 4)
+            }
+            Call(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(Token::text)
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        if self.peek() == Some(text) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> EvalexprResult<()> {
+        match self.eat(text) {
+            true => Ok(()),
+            false => error(format!(
+                "Expected \"{}\" but found \"{}\"",
+                text,
+                self.peek().unwrap_or("the end")
+            )),
+        }
+    }
+
+    fn sum(&mut self) -> EvalexprResult<Expr> {
+        let mut expr = self.product()?;
+        loop {
+            if self.eat("+") {
+                expr = add(expr, self.product()?);
+            } else if self.eat("-") {
+                expr = sub(expr, self.product()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn product(&mut self) -> EvalexprResult<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            if self.eat("*") {
+                expr = mul(expr, self.unary()?);
+            } else if self.eat("/") {
+                expr = div(expr, self.unary()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> EvalexprResult<Expr> {
+        if self.eat("-") {
+            Ok(neg(self.unary()?))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> EvalexprResult<Expr> {
+        let base = self.primary()?;
+        if self.eat("^") {
+            Ok(pow(base, self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> EvalexprResult<Expr> {
+        let token = match self.tokens.get(self.pos) {
+            Some(t) => *t,
+            None => return error(String::from("Unexpected end of expression")),
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => match preprocess::prefixed_int(n) {
+                Some(i) => Ok(num(i as f64)),
+                None => n
+                    .parse()
+                    .map(num)
+                    .or_else(|_| error(format!("Invalid number {}", n))),
+            },
+            Token::Ident(name) if self.eat("(") => {
+                let mut args = vec![self.sum()?];
+                while self.eat(",") {
+                    args.push(self.sum()?);
+                }
+                self.expect(")")?;
+                Ok(Call(name.to_string(), args))
+            }
+            Token::Ident(name) => Ok(Var(name.to_string())),
+            Token::Punct("(") => {
+                let expr = self.sum()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            t => error(format!("Can not work symbolically with \"{}\"", t.text())),
+        }
+    }
+}
+
+/// `sym::diff(expression, variable)`, the preprocessor turns `diff(e, x)` into this
+pub fn diff(value: &Value) -> EvalexprResult<Value> {
+    match value.as_tuple()?.as_slice() {
+        [expr, var] => {
+            let angle = eval::current_options().angle;
+            let expr = Expr::parse(&expr.as_string()?)?.expand(angle)?;
+            let result = expr.derivative(&var.as_string()?, angle)?.simplify();
+            Ok(Value::String(result.to_string()))
+        }
+        args => Err(EvalexprError::WrongFunctionArgumentAmount {
+            expected: 2,
+            actual: args.len(),
+        }),
+    }
+}
+
+/// `sym::simplify(expression)`, the preprocessor turns `simplify(e)` into this
+pub fn simplify(value: &Value) -> EvalexprResult<Value> {
+    let expr = Expr::parse(&value.as_string()?)?.expand(eval::current_options().angle)?;
+    Ok(Value::String(expr.simplify().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn derivative(source: &str, angle: AngleUnit) -> String {
+        Expr::parse(source)
+            .unwrap()
+            .derivative("x", angle)
+            .unwrap()
+            .simplify()
+            .to_string()
+    }
+
+    fn simplified(source: &str) -> String {
+        Expr::parse(source).unwrap().simplify().to_string()
+    }
+
+    #[test]
+    fn derivatives() {
+        let d = |source| derivative(source, AngleUnit::Rad);
+        assert_eq!(d("5"), "0");
+        assert_eq!(d("y * x"), "y");
+        assert_eq!(d("3*x^2 + 2*x + 1"), "6 * x + 2");
+        assert_eq!(d("1 / x"), "-1 / x^2");
+        assert_eq!(d("x * math::sin(x)"), "math::sin(x) + x * math::cos(x)");
+        assert_eq!(d("math::cos(2*x)"), "-(2 * math::sin(2 * x))");
+        assert_eq!(d("math::exp(x)"), "math::exp(x)");
+        assert_eq!(d("math::ln(x)"), "1 / x");
+        assert_eq!(d("math::sqrt(x)"), "1 / (2 * math::sqrt(x))");
+        assert_eq!(d("2^x"), "2^x * math::ln(2)");
+        assert_eq!(d("x^x"), "x^x * (math::ln(x) + 1)");
+        assert_eq!(d("math::tan(x)"), "1 / math::cos(x)^2");
+        assert_eq!(d("math::atan(x)"), "1 / (1 + x^2)");
+    }
+
+    #[test]
+    fn derivatives_in_degrees() {
+        assert_eq!(derivative("sin(x)", AngleUnit::Deg), "cos(x) * (PI / 180)");
+    }
+
+    #[test]
+    fn simplification() {
+        assert_eq!(simplified("x + 0"), "x");
+        assert_eq!(simplified("1 * x"), "x");
+        assert_eq!(simplified("x * 0"), "0");
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("x / x"), "1");
+        assert_eq!(simplified("--x"), "x");
+        assert_eq!(simplified("2 + 3"), "5");
+        assert_eq!(simplified("2*x + 3*x"), "5 * x");
+        assert_eq!(simplified("x * x"), "x^2");
+        assert_eq!(simplified("x^1"), "x");
+        assert_eq!(simplified("x^0"), "1");
+    }
+
+    #[test]
+    fn nested_powers() {
+        assert_eq!(simplified("(x^2)^3"), "x^6");
+        // x^1 would be wrong for negative x
+        assert_eq!(simplified("(x^2)^0.5"), "(x^2)^0.5");
+    }
+
+    #[test]
+    fn nested_calls() {
+        let expanded = Expr::parse("diff(x^2, x) + 1")
+            .unwrap()
+            .expand(AngleUnit::Rad)
+            .unwrap()
+            .simplify();
+        assert_eq!(expanded.to_string(), "2 * x + 1");
+        assert!(Expr::parse("x +").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use {
+    gio::prelude::*,
+    std::{fs, io::Read, path::Path},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    /// UTF-8 starting with a byte order mark
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+}
+
+/// How a document is stored on disk, kept so saving writes it back the same way
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TextFile {
+    /// Content with `\n` line endings
+    pub content: String,
+    pub format: Format,
+}
+
+impl TextFile {
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let (encoding, text) = match bytes {
+            [0xEF, 0xBB, 0xBF, rest @ ..] => (Encoding::Utf8Bom, utf8(rest)?),
+            [0xFF, 0xFE, rest @ ..] => (Encoding::Utf16Le, utf16(rest, u16::from_le_bytes)?),
+            [0xFE, 0xFF, rest @ ..] => (Encoding::Utf16Be, utf16(rest, u16::from_be_bytes)?),
+            _ => match std::str::from_utf8(bytes) {
+                Ok(s) => (Encoding::Utf8, s.to_string()),
+                // Every byte sequence is valid Latin-1
+                Err(_) => (Encoding::Latin1, bytes.iter().map(|&b| b as char).collect()),
+            },
+        };
+
+        Ok(Self {
+            content: text.replace("\r\n", "\n").replace('\r', "\n"),
+            format: Format {
+                encoding,
+                line_ending: line_ending(&text),
+            },
+        })
+    }
+}
+
+/// The most common line ending of `text`, `\n` if it has no line breaks or it is a tie
+fn line_ending(text: &str) -> LineEnding {
+    let crlf = text.matches("\r\n").count();
+    let cr = text.matches('\r').count() - crlf;
+    let lf = text.matches('\n').count() - crlf;
+    if lf >= crlf && lf >= cr {
+        LineEnding::Lf
+    } else if crlf >= cr {
+        LineEnding::CrLf
+    } else {
+        LineEnding::Cr
+    }
+}
+
+/// Encode `content`, which uses `\n` line endings, in `format`
+pub fn encode(content: &str, format: Format) -> Result<Vec<u8>, String> {
+    let text = match format.line_ending {
+        LineEnding::Lf => content.to_string(),
+        ending => content.replace('\n', ending.as_str()),
+    };
+
+    Ok(match format.encoding {
+        Encoding::Utf8 => text.into_bytes(),
+        Encoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF][..], text.as_bytes()].concat(),
+        Encoding::Utf16Le => [0xFF, 0xFE]
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect(),
+        Encoding::Utf16Be => [0xFE, 0xFF]
+            .iter()
+            .copied()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect(),
+        Encoding::Latin1 => text
+            .chars()
+            .map(|c| match c as u32 {
+                c @ 0..=0xFF => Ok(c as u8),
+                _ => Err(format!(
+                    "\"{}\" can not be saved in the Latin-1 encoding",
+                    c
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
+/// Read all of `file`, whatever its size
+pub fn load(file: &gio::File) -> Result<TextFile, String> {
+    let mut bytes = Vec::new();
+    file.read(gio::NONE_CANCELLABLE)
+        .map_err(|e| e.to_string())?
+        .into_read()
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    TextFile::decode(&bytes)
+}
+
+pub fn save(path: &Path, content: &str, format: Format) -> Result<(), String> {
+    fs::write(path, encode(content, format)?).map_err(|e| e.to_string())
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+}
+
+fn utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<String, String> {
+    if bytes.len() % 2 != 0 {
+        return Err(String::from("Invalid UTF-16, odd number of bytes"));
+    }
+    let units = bytes
+        .chunks(2)
+        .map(|c| from_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16(&units).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> TextFile {
+        let text = TextFile::decode(bytes).unwrap();
+        assert_eq!(encode(&text.content, text.format).unwrap(), bytes);
+        text
+    }
+
+    #[test]
+    fn encodings() {
+        let text = round_trip(b"\xEF\xBB\xBFx = 1");
+        assert_eq!(text.content, "x = 1");
+        assert_eq!(text.format.encoding, Encoding::Utf8Bom);
+
+        let text = round_trip(b"\xFF\xFEx\x00=\x00\xAC\x20");
+        assert_eq!(text.content, "x=\u{20AC}");
+        assert_eq!(text.format.encoding, Encoding::Utf16Le);
+
+        let text = round_trip(b"\xFE\xFF\x00x\x00=\x20\xAC");
+        assert_eq!(text.content, "x=\u{20AC}");
+        assert_eq!(text.format.encoding, Encoding::Utf16Be);
+
+        let text = round_trip(b"caf\xE9");
+        assert_eq!(text.content, "caf\u{E9}");
+        assert_eq!(text.format.encoding, Encoding::Latin1);
+
+        let text = round_trip("caf\u{E9}".as_bytes());
+        assert_eq!(text.format.encoding, Encoding::Utf8);
+    }
+
+    #[test]
+    fn invalid_utf16() {
+        assert!(TextFile::decode(b"\xFF\xFEx").is_err());
+    }
+
+    #[test]
+    fn unencodable_latin1() {
+        let format = Format {
+            encoding: Encoding::Latin1,
+            line_ending: LineEnding::Lf,
+        };
+        assert!(encode("\u{20AC}", format).is_err());
+    }
+
+    #[test]
+    fn line_endings() {
+        let text = round_trip(b"a\r\nb\r\n");
+        assert_eq!(text.content, "a\nb\n");
+        assert_eq!(text.format.line_ending, LineEnding::CrLf);
+
+        assert_eq!(round_trip(b"a\rb").format.line_ending, LineEnding::Cr);
+        assert_eq!(round_trip(b"a").format.line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn most_common_line_ending() {
+        let text = TextFile::decode(b"a\rb\nc\nd\n").unwrap();
+        assert_eq!(text.content, "a\nb\nc\nd\n");
+        assert_eq!(text.format.line_ending, LineEnding::Lf);
+
+        let text = TextFile::decode(b"a\nb\r\nc\r\n").unwrap();
+        assert_eq!(text.format.line_ending, LineEnding::CrLf);
+    }
+}
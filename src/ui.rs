@@ -1,13 +1,34 @@
 use {
-    crate::{get_obj, resource},
-    gio::{prelude::*, SettingsBindFlags, SettingsExt, SimpleAction, SimpleActionGroup},
+    crate::{
+        builtins,
+        eval::{self, EvalOptions},
+        get_obj,
+        header::{self, Header},
+        history::History,
+        plot::Plot,
+        preferences::Preferences,
+        recent,
+        recovery::{self, Snapshot},
+        report::{self, ReportFormat},
+        resource,
+        textfile::{self, Format},
+    },
+    gio::{prelude::*, SettingsExt, SimpleAction, SimpleActionGroup},
     glib::clone,
     gtk::{
-        prelude::*, AboutDialog, ApplicationWindow, Builder, Button, Clipboard, Entry,
-        FileChooserAction, FileChooserNative, FileFilter, InfoBar, Label, ResponseType, Stack,
-        TextBuffer, TextView,
+        prelude::*, AboutDialog, ApplicationWindow, Builder, Button, Clipboard, DestDefaults,
+        Entry, FileChooserAction, FileChooserNative, FileFilter, IconSize, InfoBar, Inhibit, Label,
+        ListBox, ListBoxRow, ListStore, Orientation, Popover, RecentManager, ResponseType,
+        SearchEntry, SelectionData, Stack, TargetList, TextBuffer, TextView, TreeModelFilter,
+        TreeView,
+    },
+    std::{
+        cell::RefCell,
+        fs,
+        path::{Path, PathBuf},
+        rc::Rc,
+        time::SystemTime,
     },
-    std::{cell::RefCell, fs, path::PathBuf, rc::Rc},
 };
 
 #[derive(Debug)]
@@ -15,7 +36,10 @@ pub struct Ui {
     main_window: ApplicationWindow,
     input: TextView,
     input_buffer: TextBuffer,
+    history: History,
     result: Entry,
+    /// Angle unit in effect, in the header of the math page
+    angle_label: Label,
     stack: Stack,
     about_button: Button,
     about_dialog: AboutDialog,
@@ -23,8 +47,21 @@ pub struct Ui {
     info_bar_label: Label,
     open_dialog: FileChooserNative,
     save_dialog: FileChooserNative,
+    export_dialog: FileChooserNative,
+    recent_popover: Popover,
+    recent_list: ListBox,
+    start_recent_list: ListBox,
+    recent_documents: RefCell<Vec<recent::Document>>,
+    plot: Rc<Plot>,
+    preferences: Rc<Preferences>,
     edited: RefCell<bool>,
     path: RefCell<Option<PathBuf>>,
+    format: RefCell<Format>,
+    monitor: RefCell<Option<gio::FileMonitor>>,
+    /// Modification time of the file when it was last loaded or saved
+    disk_modified: RefCell<Option<SystemTime>>,
+    autosave_source: RefCell<Option<glib::SourceId>>,
+    snapshot: Snapshot,
     settings: gio::Settings,
 }
 
@@ -32,18 +69,13 @@ impl Ui {
     pub fn new(settings: &gio::Settings) -> Rc<Self> {
         let b = Builder::from_resource(resource!("ui/main"));
 
-        settings.bind(
-            "ask-save-on-exit",
-            &get_obj!(b, gtk::Switch, "ask-save-switch"),
-            "active",
-            SettingsBindFlags::DEFAULT,
-        );
-
         let this = Rc::new(Self {
             main_window: get_obj!(b, "main-window"),
             input: get_obj!(b, "input"),
             input_buffer: get_obj!(b, "input-buffer"),
+            history: History::new(&get_obj!(b, "input-buffer")),
             result: get_obj!(b, "result"),
+            angle_label: get_obj!(b, "angle-label"),
             stack: get_obj!(b, "stack"),
             about_button: get_obj!(b, "about-button"),
             about_dialog: get_obj!(b, "about-dialog"),
@@ -63,14 +95,80 @@ impl Ui {
                 None,
                 None,
             ),
+            export_dialog: FileChooserNative::new(
+                None,
+                Some(&get_obj!(b, ApplicationWindow, "main-window")),
+                FileChooserAction::Save,
+                None,
+                None,
+            ),
+            recent_popover: get_obj!(b, "recent-popover"),
+            recent_list: get_obj!(b, "recent-list"),
+            start_recent_list: get_obj!(b, "start-recent-list"),
+            recent_documents: RefCell::new(Vec::new()),
+            plot: Plot::new(&b),
+            preferences: Preferences::new(&b, settings),
             edited: RefCell::new(false),
             path: RefCell::new(None),
+            format: RefCell::new(Format::default()),
+            monitor: RefCell::new(None),
+            disk_modified: RefCell::new(None),
+            autosave_source: RefCell::new(None),
+            snapshot: Snapshot::create(),
             settings: settings.clone(),
         });
 
         let file_filter = FileFilter::new();
         file_filter.add_pattern("*.mee");
         this.open_dialog.set_filter(&file_filter);
+        this.open_dialog.set_select_multiple(true);
+
+        // Help page functions, generated from the builtin registry
+        let functions: ListStore = get_obj!(b, "functions");
+        for builtin in builtins::all() {
+            functions.insert_with_values(
+                None,
+                &[0, 1, 2, 3, 4, 5],
+                &[
+                    &builtin.name,
+                    &builtin.arity,
+                    &builtin.types,
+                    &builtin.description,
+                    &builtin.example,
+                    &builtin.snippet(),
+                ],
+            );
+        }
+
+        let functions_filter: TreeModelFilter = get_obj!(b, "functions-filter");
+        let functions_search: SearchEntry = get_obj!(b, "functions-search");
+        functions_filter.set_visible_func(clone!(@strong functions_search => move |model, iter| {
+            let query = functions_search.get_text().to_lowercase();
+            query.is_empty()
+                || [0, 3].iter().any(|&col| {
+                    model
+                        .get_value(iter, col)
+                        .get::<String>()
+                        .ok()
+                        .flatten()
+                        .map(|v| v.to_lowercase().contains(&query))
+                        .unwrap_or(false)
+                })
+        }));
+        functions_search.connect_search_changed(move |_| functions_filter.refilter());
+
+        // Insert the double-clicked function into the editor
+        get_obj!(b, TreeView, "functions-view").connect_row_activated(
+            clone!(@strong this => move |view, path, _| {
+                if let Some(snippet) = view
+                    .get_model()
+                    .and_then(|m| m.get_iter(path).map(|iter| m.get_value(&iter, 5)))
+                    .and_then(|v| v.get::<String>().ok().flatten())
+                {
+                    this.insert_snippet(&snippet);
+                }
+            }),
+        );
 
         // Infobar close button
         this.info_bar.connect_response(|ib, _| {
@@ -85,36 +183,123 @@ impl Ui {
                 this.about_dialog.hide();
             }));
 
-        // Copy result (secondary icon click)
-        this.result.connect_icon_release(|entry, pos, _evt_btn| {
-            if pos == gtk::EntryIconPosition::Secondary {
-                Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&entry.get_text().to_string());
-            }
-        });
+        // Insert result into the editor (primary icon click) or copy it (secondary icon click)
+        this.result
+            .connect_icon_release(clone!(@strong this => move |entry, pos, _evt_btn| {
+                let text = entry.get_text().to_string();
+                match pos {
+                    gtk::EntryIconPosition::Primary => this.insert_text(&text),
+                    _ => Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&text),
+                }
+            }));
+
+        // Drag and drop, .mee files are opened and anything else is inserted into the editor
+        this.main_window
+            .drag_dest_set(DestDefaults::ALL, &[], gdk::DragAction::COPY);
+        this.main_window.drag_dest_add_uri_targets();
+        this.main_window.drag_dest_add_text_targets();
+        this.main_window.connect_drag_data_received(
+            clone!(@strong this => move |_, _, _, _, data, _, _| this.drop_data(data)),
+        );
+
+        // The editor inserts dropped text itself, files are preferred over text
+        // so a dragged file is not inserted as its path
+        let input_targets = TargetList::new(&[]);
+        input_targets.add_uri_targets(0);
+        input_targets.add_text_targets(0);
+        this.input.drag_dest_set_target_list(Some(&input_targets));
+        this.input.connect_drag_data_received(
+            clone!(@strong this => move |input, context, _, _, data, _, time| {
+                if !data.get_uris().is_empty() {
+                    input.stop_signal_emission("drag-data-received");
+                    context.drag_finish(true, false, time);
+                    this.drop_data(data);
+                }
+            }),
+        );
 
         // Do math
         this.input_buffer
             .connect_changed(clone!(@strong this => move |_| {
                 this.edited.replace(true);
                 this.update_title();
-                this.eval();
+                if this.settings.get_string("eval-trigger").as_deref() != Some("manual") {
+                    this.eval();
+                }
+            }));
+
+        // Plot export
+        this.plot
+            .export_png_button
+            .connect_clicked(clone!(@strong this => move |_| {
+                if let Some(path) = this.ask_export_path("plot.png") {
+                    match this.plot.export_png(&path) {
+                        Ok(_) => this.show_info("Plot exported"),
+                        Err(e) => this.show_error(&e),
+                    }
+                }
+            }));
+        this.plot
+            .export_svg_button
+            .connect_clicked(clone!(@strong this => move |_| {
+                if let Some(path) = this.ask_export_path("plot.svg") {
+                    match this.plot.export_svg(&path) {
+                        Ok(_) => this.show_info("Plot exported"),
+                        Err(e) => this.show_error(&e),
+                    }
+                }
+            }));
+
+        // Apply evaluation and autosave preferences live
+        this.settings
+            .connect_changed(clone!(@strong this => move |_, key| {
+                match key {
+                    "editor-font" | "result-font" => this.apply_fonts(),
+                    "eval-trigger" | "precision-mode" | "angle-unit" | "programmer-mode" | "word-size" => {
+                        this.eval()
+                    }
+                    "autosave" | "autosave-interval" => this.setup_autosave(),
+                    "pinned-documents" => this.update_recent(),
+                    k if k.starts_with("result-") => this.eval(),
+                    _ => {}
+                }
             }));
+        this.setup_autosave();
+        this.apply_fonts();
+
+        // Crash recovery snapshots
+        let weak = Rc::downgrade(&this);
+        glib::timeout_add_seconds_local(recovery::INTERVAL, move || match weak.upgrade() {
+            Some(this) => {
+                if *this.edited.borrow() {
+                    let path = this.path.borrow().clone();
+                    if let Err(e) = this.snapshot.save(&this.get_content(), path.as_deref()) {
+                        this.show_error(&format!("Could not save a recovery snapshot: {}", e));
+                    }
+                }
+                glib::Continue(true)
+            }
+            None => glib::Continue(false),
+        });
+
+        // Recent documents, in the header bar and on the start page
+        for list in &[&this.recent_list, &this.start_recent_list] {
+            list.connect_row_activated(clone!(@strong this => move |_, row| {
+                this.open_recent(row.get_index());
+            }));
+        }
+        if let Some(manager) = RecentManager::get_default() {
+            manager.connect_changed(clone!(@strong this => move |_| this.update_recent()));
+        }
+        this.update_recent();
 
         let file_ag = this.new_action_group("file");
 
         let open_action = SimpleAction::new("open", None);
         open_action.connect_activate(clone!(@strong this => move |_, _| {
-            if this.stack.get_visible_child_name() == Some("math".into()) {
-                if *this.edited.borrow() && this.settings.get_boolean("ask-save-on-exit") {
-                    if this.ask_save_file() {
-                        this.save_file();
-                        this.open_file();
-                    } else {
-                        this.open_file();
-                    }
-                } else {
-                    this.open_file();
-                }
+            let page = this.stack.get_visible_child_name();
+            if matches!(page.as_deref(), Some("math") | Some("start")) {
+                this.open_file();
             }
         }));
         file_ag.add_action(&open_action);
@@ -127,6 +312,93 @@ impl Ui {
         }));
         file_ag.add_action(&save_action);
 
+        let save_as_action = SimpleAction::new("save-as", None);
+        save_as_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.save_as();
+        }));
+        file_ag.add_action(&save_as_action);
+
+        // Reports of the document with its results, the parameter is the format name
+        let export_action = SimpleAction::new("export", Some(glib::VariantTy::new("s").unwrap()));
+        export_action.connect_activate(clone!(@strong this => move |_, param| {
+            if let Some(format) = param
+                .and_then(|p| p.get_str())
+                .and_then(ReportFormat::from_name)
+            {
+                this.export_report(format);
+            }
+        }));
+        file_ag.add_action(&export_action);
+
+        let edit_ag = this.new_action_group("edit");
+
+        let undo_action = SimpleAction::new("undo", None);
+        undo_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.history.undo();
+        }));
+        edit_ag.add_action(&undo_action);
+
+        let redo_action = SimpleAction::new("redo", None);
+        redo_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.history.redo();
+        }));
+        edit_ag.add_action(&redo_action);
+
+        let app_ag = this.new_action_group("app");
+
+        let to_math_action = SimpleAction::new("math", None);
+        to_math_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.show_math();
+        }));
+        app_ag.add_action(&to_math_action);
+
+        let to_plot_action = SimpleAction::new("plot", None);
+        to_plot_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.show_plot();
+        }));
+        app_ag.add_action(&to_plot_action);
+
+        let to_help_action = SimpleAction::new("help", None);
+        to_help_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.show_help();
+        }));
+        app_ag.add_action(&to_help_action);
+
+        let eval_action = SimpleAction::new("eval", None);
+        eval_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.eval();
+        }));
+        app_ag.add_action(&eval_action);
+
+        let preferences_action = SimpleAction::new("preferences", None);
+        preferences_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.show_preferences();
+        }));
+        app_ag.add_action(&preferences_action);
+
+        // Close this window only, asking to save its document first
+        let close_action = SimpleAction::new("close", None);
+        close_action.connect_activate(clone!(@strong this => move |_, _| {
+            this.main_window.close();
+        }));
+        this.main_window.add_action(&close_action);
+
+        // Close every window, each one asks to save its own document
+        let quit_action = SimpleAction::new("quit", None);
+        quit_action.connect_activate(clone!(@strong this => move |_, _| {
+            if let Some(app) = this.main_window.get_application() {
+                for window in app.get_windows() {
+                    window.close();
+                }
+            }
+        }));
+        app_ag.add_action(&quit_action);
+
+        this.main_window
+            .connect_delete_event(clone!(@strong this => move |_, _| {
+                Inhibit(!this.quit())
+            }));
+
         this
     }
 
@@ -152,10 +424,31 @@ impl Ui {
         self.stack.set_visible_child_name("math");
     }
 
+    pub fn show_plot(&self) {
+        self.stack.set_visible_child_name("plot");
+    }
+
     pub fn show_help(&self) {
         self.stack.set_visible_child_name("help");
     }
 
+    /// Show the start page if there is no document to work on
+    pub fn show_start(&self) {
+        if self.path.borrow().is_none() && self.get_content().is_empty() {
+            self.stack.set_visible_child_name("start");
+        }
+    }
+
+    pub fn show_preferences(&self) {
+        self.preferences.show();
+    }
+
+    fn apply_fonts(&self) {
+        if let Err(e) = self.preferences.apply_fonts() {
+            self.show_error(&e);
+        }
+    }
+
     pub fn show_info(&self, msg: &str) {
         self.info_bar.set_message_type(gtk::MessageType::Info);
         self.info_bar_label.set_text(msg);
@@ -179,77 +472,519 @@ impl Ui {
         self.edited.replace(edited);
     }
 
-    pub fn set_path(&self, path: PathBuf) {
+    pub fn set_path(self: &Rc<Self>, path: PathBuf) {
+        self.watch(&path);
         self.path.replace(Some(path));
     }
 
-    pub fn quit(&self) {
-        if *self.edited.borrow()
-            && self.settings.get_boolean("ask-save-on-exit")
-            && self.ask_save_file()
-        {
-            self.save_file()
+    /// Replace the document with the content of `file`
+    pub fn open(self: &Rc<Self>, file: &gio::File) {
+        match textfile::load(file) {
+            Ok(text) => {
+                match file.get_path() {
+                    Some(path) => {
+                        self.add_recent(&path);
+                        self.set_path(path);
+                    }
+                    // Not a local file, saving asks where to
+                    None => {
+                        self.unwatch();
+                        self.path.replace(None);
+                    }
+                }
+                self.format.replace(text.format);
+                self.set_input(&text.content);
+                self.history.clear();
+                self.set_edited(false);
+                self.update_title();
+                self.show_math();
+                let header = Header::parse(&text.content);
+                if header.is_newer() {
+                    self.show_info(&format!(
+                        "This document uses version {} of the file format, only version {} is \
+                         supported and some settings may be ignored",
+                        header.version.unwrap_or_default(),
+                        header::VERSION
+                    ));
+                }
+            }
+            Err(e) => self.show_error(&format!("Could not open {}: {}", file.get_parse_name(), e)),
+        }
+    }
+
+    /// Open the first of `files` in this window and each of the others in a new one, nothing is
+    /// opened if the user cancels saving the document it replaces
+    pub fn open_all(self: &Rc<Self>, files: &[gio::File]) {
+        if files.is_empty() || !self.maybe_save() {
+            return;
+        }
+        if let Some((first, others)) = files.split_first() {
+            self.open(first);
+            for file in others {
+                let ui = Self::new(&self.settings);
+                if let Some(app) = self.main_window.get_application() {
+                    ui.set_app(&app);
+                }
+                ui.show();
+                ui.open(file);
+            }
+        }
+    }
+
+    /// Ask to save changes before the window closes, `false` if closing was cancelled
+    pub fn quit(self: &Rc<Self>) -> bool {
+        if !self.maybe_save() {
+            return false;
+        }
+        // Closing normally, the snapshot is only for crashes
+        self.discard_snapshot(&self.snapshot);
+        true
+    }
+
+    /// Offer to restore documents left unsaved by a crashed session
+    pub fn restore_snapshots(self: &Rc<Self>) {
+        for snapshot in Snapshot::orphaned() {
+            let name = snapshot
+                .original()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|| String::from("an untitled document"));
+            let time = snapshot
+                .modified()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .and_then(|d| glib::DateTime::from_unix_local(d.as_secs() as i64).format("%c"))
+                .map(|t| t.to_string())
+                .unwrap_or_default();
+
+            let dialog = gtk::MessageDialog::new(
+                Some(&self.main_window),
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Question,
+                gtk::ButtonsType::YesNo,
+                &format!("Restore unsaved changes to {}?", name),
+            );
+            dialog.set_property_secondary_text(Some(&format!(
+                "The changes are from {}. Choosing No discards them.",
+                time
+            )));
+            let restore = dialog.run() == ResponseType::Yes;
+            dialog.hide();
+
+            if restore {
+                match snapshot.content() {
+                    Ok(content) => {
+                        if let Some(path) = snapshot.original() {
+                            self.set_path(path);
+                        }
+                        self.set_input(&content);
+                        self.update_title();
+                        self.discard_snapshot(&snapshot);
+                    }
+                    Err(e) => self.show_error(&e.to_string()),
+                }
+                // One document per window, any other snapshots are offered next time
+                break;
+            }
+            self.discard_snapshot(&snapshot);
+        }
+    }
+
+    fn discard_snapshot(&self, snapshot: &Snapshot) {
+        if let Err(e) = snapshot.discard() {
+            self.show_error(&format!("Could not remove a recovery snapshot: {}", e));
         }
     }
 
-    fn ask_save_file(&self) -> bool {
+    fn setup_autosave(self: &Rc<Self>) {
+        if let Some(source) = self.autosave_source.borrow_mut().take() {
+            glib::source_remove(source);
+        }
+        if self.settings.get_boolean("autosave") {
+            let interval = self.settings.get_int("autosave-interval").max(5) as u32;
+            let this = Rc::downgrade(self);
+            self.autosave_source
+                .replace(Some(glib::timeout_add_seconds_local(interval, move || {
+                    match this.upgrade() {
+                        Some(this) => {
+                            // Untitled documents are left alone, there is nowhere to save them,
+                            // and so are files changed by another program
+                            if *this.edited.borrow()
+                                && this.path.borrow().is_some()
+                                && !this.newer_on_disk()
+                            {
+                                this.save_file();
+                            }
+                            glib::Continue(true)
+                        }
+                        None => glib::Continue(false),
+                    }
+                })));
+        }
+    }
+
+    /// Give the user a chance to save changes before the document is closed or replaced,
+    /// `false` if they cancelled
+    fn maybe_save(self: &Rc<Self>) -> bool {
+        if !*self.edited.borrow() || !self.settings.get_boolean("ask-save-on-exit") {
+            return true;
+        }
+        match self.ask_save_file() {
+            ResponseType::Accept => self.save_file(),
+            ResponseType::Reject => true,
+            _ => false,
+        }
+    }
+
+    /// Save, Discard or Cancel, answered with `Accept`, `Reject` or `Cancel`
+    fn ask_save_file(&self) -> ResponseType {
+        let name = self
+            .path
+            .borrow()
+            .as_ref()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            .unwrap_or_else(|| String::from("the untitled document"));
         let dialog = gtk::MessageDialog::new(
             Some(&self.main_window),
             gtk::DialogFlags::MODAL,
             gtk::MessageType::Question,
-            gtk::ButtonsType::YesNo,
-            "Save file?",
+            gtk::ButtonsType::None,
+            &format!("Save changes to {}?", name),
         );
-        if dialog.run() == ResponseType::Yes {
-            dialog.hide();
-            true
+        dialog.set_property_secondary_text(Some("Your changes are lost if you don't save them."));
+        dialog.add_button("_Discard", ResponseType::Reject);
+        dialog.add_button("_Cancel", ResponseType::Cancel);
+        dialog.add_button("_Save", ResponseType::Accept);
+        dialog.set_default_response(ResponseType::Accept);
+        let response = dialog.run();
+        dialog.hide();
+        response
+    }
+
+    fn ask_export_path(&self, name: &str) -> Option<PathBuf> {
+        self.export_dialog.set_current_name(name);
+        if self.export_dialog.run() == ResponseType::Accept {
+            self.export_dialog.get_filename()
         } else {
-            dialog.hide();
-            false
+            None
+        }
+    }
+
+    fn drop_data(self: &Rc<Self>, data: &SelectionData) {
+        let files = data
+            .get_uris()
+            .iter()
+            .map(|uri| gio::File::new_for_uri(uri))
+            .collect::<Vec<_>>();
+        let text = data.get_text();
+        // Let the drop finish before asking to save
+        glib::idle_add_local(clone!(@strong self as this => move || {
+            match text {
+                Some(ref text) if files.is_empty() => this.insert_text(text),
+                _ => this.drop_files(&files),
+            }
+            glib::Continue(false)
+        }));
+    }
+
+    fn drop_files(self: &Rc<Self>, files: &[gio::File]) {
+        let (documents, others): (Vec<_>, Vec<_>) = files.iter().cloned().partition(|f| {
+            f.get_basename()
+                .and_then(|name| name.extension().map(|e| e == "mee"))
+                .unwrap_or(false)
+        });
+
+        for file in others {
+            match textfile::load(&file) {
+                Ok(text) => self.insert_text(&text.content),
+                Err(e) => self.show_error(&format!(
+                    "Could not insert {}: {}",
+                    file.get_parse_name(),
+                    e
+                )),
+            }
+        }
+        self.open_all(&documents);
+    }
+
+    fn insert_text(&self, text: &str) {
+        self.show_math();
+        self.input_buffer.insert_at_cursor(text);
+        self.input.grab_focus();
+    }
+
+    fn insert_snippet(&self, snippet: &str) {
+        self.show_math();
+        self.input_buffer.insert_at_cursor(snippet);
+        // Place the cursor between the parentheses of a function call
+        if snippet.ends_with("()") {
+            if let Some(mark) = self.input_buffer.get_insert() {
+                let mut iter = self.input_buffer.get_iter_at_mark(&mark);
+                iter.backward_char();
+                self.input_buffer.place_cursor(&iter);
+            }
         }
+        self.input.grab_focus();
     }
 
-    fn open_file(&self) {
+    fn open_file(self: &Rc<Self>) {
         if self.open_dialog.run() == ResponseType::Accept {
-            let path = self.open_dialog.get_filename().unwrap();
-            match fs::read_to_string(&path) {
-                Ok(content) => {
-                    self.set_path(path);
-                    self.set_input(&content);
-                    self.update_title();
-                }
-                Err(e) => self.show_error(&e.to_string()),
+            self.open_all(&self.open_dialog.get_files());
+        }
+    }
+
+    fn open_path(self: &Rc<Self>, path: PathBuf) {
+        self.open(&gio::File::new_for_path(path));
+    }
+
+    fn open_recent(self: &Rc<Self>, index: i32) {
+        self.recent_popover.popdown();
+        let document = self.recent_documents.borrow().get(index as usize).cloned();
+        if let Some(document) = document {
+            if self.maybe_save() {
+                self.open_path(document.path);
+            }
+        }
+    }
+
+    fn add_recent(&self, path: &Path) {
+        if let Err(e) = recent::add(path) {
+            self.show_error(&format!(
+                "Could not add {} to recent documents: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+
+    fn update_recent(self: &Rc<Self>) {
+        let documents = recent::documents(&self.settings);
+        for list in &[&self.recent_list, &self.start_recent_list] {
+            for row in list.get_children() {
+                list.remove(&row);
+            }
+            for document in &documents {
+                list.add(&self.recent_row(document));
+            }
+        }
+        self.recent_documents.replace(documents);
+    }
+
+    fn recent_row(self: &Rc<Self>, document: &recent::Document) -> ListBoxRow {
+        let name = Label::new(Some(&document.name()));
+        name.set_halign(gtk::Align::Start);
+        let dir = Label::new(Some(&document.dir()));
+        dir.set_halign(gtk::Align::Start);
+        dir.set_ellipsize(pango::EllipsizeMode::Start);
+        dir.get_style_context().add_class("dim-label");
+
+        let labels = gtk::Box::new(Orientation::Vertical, 2);
+        labels.pack_start(&name, false, false, 0);
+        labels.pack_start(&dir, false, false, 0);
+
+        let pin = Button::from_icon_name(
+            Some(if document.pinned {
+                "starred-symbolic"
+            } else {
+                "non-starred-symbolic"
+            }),
+            IconSize::Button,
+        );
+        pin.set_relief(gtk::ReliefStyle::None);
+        pin.set_valign(gtk::Align::Center);
+        pin.set_tooltip_text(Some(if document.pinned { "Unpin" } else { "Pin" }));
+        let (path, pinned) = (document.path.clone(), document.pinned);
+        pin.connect_clicked(clone!(@strong self as this => move |_| {
+            if let Err(e) = recent::set_pinned(&this.settings, &path, !pinned) {
+                this.show_error(&format!("Could not update the pinned documents: {}", e));
+            }
+        }));
+
+        let row_box = gtk::Box::new(Orientation::Horizontal, 12);
+        row_box.set_border_width(6);
+        row_box.set_tooltip_text(Some(&document.path.to_string_lossy()));
+        row_box.pack_start(&labels, true, true, 0);
+        row_box.pack_end(&pin, false, false, 0);
+
+        let row = ListBoxRow::new();
+        row.add(&row_box);
+        row.show_all();
+        row
+    }
+
+    /// Stop following changes to the file of the previous document
+    fn unwatch(&self) {
+        if let Some(monitor) = self.monitor.borrow_mut().take() {
+            monitor.cancel();
+        }
+        self.disk_modified.replace(None);
+    }
+
+    /// Follow changes made to the file at `path` by other programs
+    fn watch(self: &Rc<Self>, path: &Path) {
+        self.unwatch();
+        self.disk_modified.replace(disk_modified(path));
+
+        match gio::File::new_for_path(path)
+            .monitor_file(gio::FileMonitorFlags::NONE, gio::NONE_CANCELLABLE)
+        {
+            Ok(monitor) => {
+                let this = Rc::downgrade(self);
+                monitor.connect_changed(move |_, _, _, event| {
+                    if let Some(this) = this.upgrade() {
+                        if matches!(
+                            event,
+                            gio::FileMonitorEvent::ChangesDoneHint | gio::FileMonitorEvent::Created
+                        ) && this.newer_on_disk()
+                        {
+                            this.changed_on_disk();
+                        }
+                    }
+                });
+                self.monitor.replace(Some(monitor));
             }
+            Err(e) => self.show_error(&format!(
+                "Changes made by other programs are not shown: {}",
+                e
+            )),
+        }
+    }
+
+    /// Whether the file was modified since it was last loaded or saved
+    fn newer_on_disk(&self) -> bool {
+        match (self.path.borrow().as_ref(), *self.disk_modified.borrow()) {
+            (Some(path), Some(known)) => disk_modified(path).map(|m| m != known).unwrap_or(false),
+            _ => false,
         }
     }
 
-    fn save_file(&self) {
+    fn changed_on_disk(&self) {
+        if !*self.edited.borrow() {
+            self.reload();
+            self.show_info("The file was changed by another program and has been reloaded");
+            return;
+        }
+
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.main_window),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::YesNo,
+            "The file was changed by another program. Reload it?",
+        );
+        dialog.set_property_secondary_text(Some("Reloading discards your unsaved changes."));
+        let reload = dialog.run() == ResponseType::Yes;
+        dialog.hide();
+        if reload {
+            self.reload();
+        }
+    }
+
+    fn reload(&self) {
+        let path = match self.path.borrow().clone() {
+            Some(path) => path,
+            None => return,
+        };
+        match textfile::load(&gio::File::new_for_path(&path)) {
+            Ok(text) => {
+                self.disk_modified.replace(disk_modified(&path));
+                self.format.replace(text.format);
+                self.set_input(&text.content);
+                self.history.clear();
+                self.set_edited(false);
+                self.update_title();
+            }
+            Err(e) => self.show_error(&e),
+        }
+    }
+
+    fn ask_overwrite(&self) -> bool {
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.main_window),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::YesNo,
+            "The file was changed by another program. Overwrite it?",
+        );
+        dialog.set_property_secondary_text(Some(
+            "Saving replaces the newer version on disk with this document.",
+        ));
+        let overwrite = dialog.run() == ResponseType::Yes;
+        dialog.hide();
+        overwrite
+    }
+
+    /// Save the document, asking where to if it is untitled, `false` if it was not saved
+    fn save_file(self: &Rc<Self>) -> bool {
         let borrow = self.path.borrow();
         let cloned_path = borrow.clone();
         drop(borrow);
         match cloned_path {
-            Some(ref path) => match fs::write(path, &self.get_content()) {
-                Ok(_) => {
-                    self.set_edited(false);
-                    self.show_info("File saved");
-                    self.update_title();
-                }
-                Err(e) => self.show_error(&e.to_string()),
-            },
-            None => {
-                if self.save_dialog.run() == ResponseType::Accept {
-                    let path = self.save_dialog.get_filename().unwrap();
-                    match fs::write(&path, &self.get_content()) {
-                        Ok(_) => {
-                            self.set_edited(false);
-                            self.set_path(path);
-                            self.show_info("File saved");
-                            self.update_title();
-                        }
-                        Err(e) => self.show_error(&e.to_string()),
+            Some(_) if self.newer_on_disk() && !self.ask_overwrite() => false,
+            Some(ref path) => {
+                let content = header::update_version(&self.get_content());
+                match textfile::save(path, &content, *self.format.borrow()) {
+                    Ok(_) => {
+                        self.add_recent(path);
+                        self.disk_modified.replace(disk_modified(path));
+                        self.set_edited(false);
+                        self.discard_snapshot(&self.snapshot);
+                        self.show_info("File saved");
+                        self.update_title();
+                        true
+                    }
+                    Err(e) => {
+                        self.show_error(&e);
+                        false
                     }
                 }
             }
+            None => self.save_as(),
+        }
+    }
+
+    /// Save the document under a new name, `false` if it was not saved
+    fn save_as(self: &Rc<Self>) -> bool {
+        if let Some(name) = self.path.borrow().as_ref().and_then(|p| p.file_name()) {
+            self.save_dialog.set_current_name(&name.to_string_lossy());
+        }
+        if self.save_dialog.run() != ResponseType::Accept {
+            return false;
+        }
+        let path = self.save_dialog.get_filename().unwrap();
+        let content = header::update_version(&self.get_content());
+        match textfile::save(&path, &content, *self.format.borrow()) {
+            Ok(_) => {
+                self.add_recent(&path);
+                self.set_edited(false);
+                self.discard_snapshot(&self.snapshot);
+                self.set_path(path);
+                self.show_info("File saved");
+                self.update_title();
+                true
+            }
+            Err(e) => {
+                self.show_error(&e);
+                false
+            }
+        }
+    }
+
+    fn export_report(&self, format: ReportFormat) {
+        let content = self.get_content();
+        let name = self
+            .path
+            .borrow()
+            .as_ref()
+            .and_then(|p| p.file_stem().map(|n| n.to_string_lossy().to_string()))
+            .unwrap_or_else(|| String::from("Untitled"));
+        let title = Header::parse(&content)
+            .title
+            .unwrap_or_else(|| name.clone());
+        if let Some(path) = self.ask_export_path(&format!("{}.{}", name, format.extension())) {
+            let options = EvalOptions::from_settings(&self.settings);
+            match fs::write(&path, report::render(&content, options, &title, format)) {
+                Ok(_) => self.show_info("Report exported"),
+                Err(e) => self.show_error(&e.to_string()),
+            }
         }
     }
 
@@ -262,12 +997,12 @@ impl Ui {
     }
 
     fn update_title(&self) {
-        let text = match self
-            .path
-            .borrow()
-            .as_ref()
-            .and_then(|p| p.to_str().map(|p| p.to_string()))
-        {
+        let text = match Header::parse(&self.get_content()).title.or_else(|| {
+            self.path
+                .borrow()
+                .as_ref()
+                .and_then(|p| p.to_str().map(|p| p.to_string()))
+        }) {
             Some(p) => format!("Math Expr Eval - {}", p),
             None => String::from("Math Expr Eval"),
         };
@@ -277,22 +1012,23 @@ impl Ui {
         }
     }
 
-    fn eval(&self) {
-        match evalexpr::eval_with_context_mut(
-            &self.get_content(),
-            &mut evalexpr::math_consts_context!().unwrap(),
-        ) {
-            Ok(val) => match val {
-                evalexpr::Value::Empty => {
-                    self.set_result("");
-                }
-                v => {
-                    self.set_result(&v.to_string());
-                }
-            },
+    pub fn eval(&self) {
+        let content = self.get_content();
+        let options = EvalOptions::from_settings(&self.settings);
+        let angle = options.with_directives(&content).angle;
+        self.angle_label.set_text(&angle.name().to_uppercase());
+        self.plot.set_source(&content, options);
+        match eval::eval_to_string(&content, options) {
+            Ok(res) => {
+                self.set_result(&res);
+            }
             Err(e) => {
                 self.set_result(&e.to_string());
             }
         }
     }
 }
+
+fn disk_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}